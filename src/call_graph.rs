@@ -0,0 +1,131 @@
+//! DOT call graphs of SPIR-V modules, as per `--dependency-graph-out`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use rspirv::{
+    binary::ParseState,
+    dr::{Module, Operand},
+    spirv::{FunctionControl, Op, Word},
+};
+
+/// Quote a string as a DOT ID.
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// The `OpName` of each ID in a module.
+fn names(module: &Module) -> HashMap<Word, &str> {
+    module
+        .debug_names
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::Name)
+        .filter_map(|inst| match inst.operands.as_slice() {
+            [Operand::IdRef(id), Operand::LiteralString(name)] => Some((*id, name.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Generate a DOT graph of the functions in the given named SPIR-V modules, with an edge from
+/// each function to those it calls, labelled with the number of call sites if more than one.
+///
+/// Each module is drawn as a cluster. Entry points are filled, functions marked `DontInline`
+/// are drawn bold, and functions marked `Inline` but still called rather than inlined are dashed.
+/// Functions inlined at compile time no longer exist in the module, so aren't drawn.
+pub fn dot<'a>(
+    modules: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> Result<String, ParseState> {
+    let mut out = String::from("digraph shaders {\n");
+    out += "    node [shape=box, fontname=monospace];\n";
+
+    for (index, (module_name, bytes)) in modules.into_iter().enumerate() {
+        let module = rspirv::dr::load_bytes(bytes)?;
+        let names = names(&module);
+        let entry_points = module
+            .entry_points
+            .iter()
+            .filter_map(|inst| match inst.operands.as_slice() {
+                [Operand::ExecutionModel(model), Operand::IdRef(id), Operand::LiteralString(name), ..] => {
+                    Some((*id, format!("{name} ({model:?})")))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let node = |id: Word| format!("m{index}_{id}");
+
+        writeln!(out, "    subgraph cluster_{index} {{").unwrap();
+        writeln!(out, "        label={};", quote(module_name)).unwrap();
+
+        for function in &module.functions {
+            let Some(def) = &function.def else {
+                continue
+            };
+            let Some(id) = def.result_id else {
+                continue
+            };
+
+            let name = names
+                .get(&id)
+                .map_or_else(|| format!("%{id}"), |name| name.to_string());
+            let control = def
+                .operands
+                .iter()
+                .find_map(|operand| match operand {
+                    Operand::FunctionControl(control) => Some(*control),
+                    _ => None,
+                })
+                .unwrap_or(FunctionControl::NONE);
+
+            let attributes = if let Some(entry_point) = entry_points.get(&id) {
+                format!(
+                    "label={}, shape=doubleoctagon, style=filled, fillcolor=gold",
+                    quote(&format!("{name}\n{entry_point}"))
+                )
+            } else if control.contains(FunctionControl::DONT_INLINE) {
+                format!("label={}, style=bold", quote(&name))
+            } else if control.contains(FunctionControl::INLINE) {
+                format!("label={}, style=dashed", quote(&name))
+            } else {
+                format!("label={}", quote(&name))
+            };
+            writeln!(out, "        {} [{attributes}];", node(id)).unwrap();
+
+            let mut calls = BTreeMap::<Word, usize>::new();
+            for inst in function.blocks.iter().flat_map(|block| &block.instructions) {
+                if inst.class.opcode != Op::FunctionCall {
+                    continue;
+                }
+
+                if let Some(Operand::IdRef(callee)) = inst.operands.first() {
+                    *calls.entry(*callee).or_default() += 1;
+                }
+            }
+
+            for (callee, count) in calls {
+                if count > 1 {
+                    writeln!(
+                        out,
+                        "        {} -> {} [label=\"{count}\"];",
+                        node(id),
+                        node(callee)
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(out, "        {} -> {};", node(id), node(callee)).unwrap();
+                }
+            }
+        }
+
+        out += "    }\n";
+    }
+
+    out += "}\n";
+    Ok(out)
+}
@@ -0,0 +1,266 @@
+//! `cargo check` pre-pass, used to report ordinary type errors without a full SPIR-V compile.
+
+use std::{
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use spirv_builder::Capability;
+use tracing::warn;
+
+use crate::diagnostics::{Diagnostic, DiagnosticCounts, DiagnosticFilter, MessageFormat};
+
+/// The outcome of a `cargo check` pre-pass.
+pub struct CheckReport {
+    pub passed: bool,
+    pub counts: DiagnosticCounts,
+    /// Diagnostics reported by the check, excluding rustc's closing summaries.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// State of a shader crate's `Cargo.lock`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockfileStatus {
+    Missing,
+    /// Present, but cargo would need to update it to build.
+    Outdated,
+    UpToDate,
+}
+
+/// Look for a `Cargo.lock` in the crate at `path_to_crate` or any of its ancestors, as for a workspace member.
+pub fn find_lockfile(path_to_crate: &Path) -> Option<PathBuf> {
+    path_to_crate
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|lockfile| lockfile.is_file())
+}
+
+/// Determine whether the crate at `path_to_crate` has an up-to-date `Cargo.lock`,
+/// by resolving its dependencies with `cargo metadata --locked`.
+///
+/// Returns `None` if cargo failed for any other reason.
+pub fn lockfile_status(path_to_crate: &Path) -> Option<LockfileStatus> {
+    if find_lockfile(path_to_crate).is_none() {
+        return Some(LockfileStatus::Missing);
+    }
+
+    let output = Command::new("cargo")
+        .args(["metadata", "--locked", "--format-version", "1"])
+        .current_dir(path_to_crate)
+        .stdout(Stdio::null())
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(LockfileStatus::UpToDate)
+    } else if String::from_utf8_lossy(&output.stderr).contains("--locked") {
+        Some(LockfileStatus::Outdated)
+    } else {
+        None
+    }
+}
+
+/// Run `cargo clean -p` for the shader crate at `path_to_crate`, so that its build script re-runs
+/// on the next build, leaving its dependencies compiled.
+pub fn cargo_clean(
+    path_to_crate: &Path,
+    target_dir: &Path,
+    target: &str,
+    release: bool,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    // `-p` takes the package name, which needn't match the crate's directory
+    let manifest = std::fs::read_to_string(path_to_crate.join("Cargo.toml"))?;
+    let manifest = manifest
+        .parse::<toml::Table>()
+        .map_err(|e| invalid(format!("Cargo.toml isn't valid TOML: {e}")))?;
+    let package = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+        .ok_or_else(|| invalid("Cargo.toml has no package name".to_string()))?;
+
+    let mut cargo = Command::new("cargo");
+    cargo
+        .args(["clean", "-p", package, "--target", target, "--target-dir"])
+        .arg(target_dir)
+        .current_dir(path_to_crate)
+        .stdout(Stdio::null());
+
+    if release {
+        cargo.arg("--release");
+    }
+
+    let output = cargo.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// How the `cargo check` pre-pass is run and reported, as per [`cargo_check`].
+pub struct CheckOptions<'a> {
+    pub target_dir: &'a Path,
+    /// Full target triple, ex. `spirv-unknown-vulkan1.1`.
+    pub target: &'a str,
+    pub release: bool,
+    /// Capabilities enabled as target features, as for the full compile.
+    pub capabilities: &'a [Capability],
+    pub message_format: MessageFormat,
+    /// Diagnostics to report. Every diagnostic is counted and returned regardless.
+    pub filter: DiagnosticFilter,
+    /// Whether rustc's rendering of diagnostics is colored.
+    pub color: bool,
+    pub locked: bool,
+    /// If set, human-readable output stops after this many errors,
+    /// followed by a line counting the errors left out.
+    pub max_errors: Option<usize>,
+}
+
+/// The environment variable holding the dynamic library search path on this platform.
+fn dylib_path_envvar() -> &'static str {
+    if cfg!(windows) {
+        "PATH"
+    } else if cfg!(target_os = "macos") {
+        "DYLD_FALLBACK_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    }
+}
+
+/// Find the `rustc_codegen_spirv` backend on the dynamic library search path, as `spirv-builder` does.
+fn find_rustc_codegen_spirv() -> io::Result<PathBuf> {
+    let filename = format!("{DLL_PREFIX}rustc_codegen_spirv{DLL_SUFFIX}");
+    std::env::var_os(dylib_path_envvar())
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(&filename))
+                .find(|path| path.is_file())
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{filename} not found in {}", dylib_path_envvar()),
+            )
+        })
+}
+
+/// The rustflags `spirv-builder` passes for `capabilities`,
+/// less those that only affect code generation, which `cargo check` skips.
+fn rustflags(backend: &Path, capabilities: &[Capability]) -> String {
+    let mut rustflags = vec![
+        format!("-Zcodegen-backend={}", backend.display()),
+        "-Zbinary-dep-depinfo".to_string(),
+        "-Csymbol-mangling-version=v0".to_string(),
+        "-Zcrate-attr=feature(register_tool)".to_string(),
+        "-Zcrate-attr=register_tool(rust_gpu)".to_string(),
+        "-Coverflow-checks=off".to_string(),
+        "-Cdebug-assertions=off".to_string(),
+        "-Zinline-mir=off".to_string(),
+    ];
+
+    if !capabilities.is_empty() {
+        let features = capabilities
+            .iter()
+            .map(|capability| format!("+{capability:?}"))
+            .collect::<Vec<_>>();
+        rustflags.push(format!("-Ctarget-feature={}", features.join(",")));
+    }
+
+    rustflags.join("\x1f")
+}
+
+/// Run `cargo check` on the shader crate at `path_to_crate`.
+///
+/// The target, profile, rustflags and `build-std` settings mirror those `spirv-builder`
+/// uses for the full compile, so the check sees the same errors.
+/// Compiler diagnostics are parsed from cargo's JSON messages, and reported as per `options`.
+pub fn cargo_check(path_to_crate: &Path, options: &CheckOptions) -> io::Result<CheckReport> {
+    let CheckOptions {
+        target_dir,
+        target,
+        release,
+        capabilities,
+        message_format,
+        filter,
+        color,
+        locked,
+        max_errors,
+    } = *options;
+
+    let mut cargo = Command::new("cargo");
+    cargo
+        .arg("check")
+        .arg(if color {
+            "--message-format=json-diagnostic-rendered-ansi"
+        } else {
+            "--message-format=json"
+        })
+        .args([
+            "-Zbuild-std=core",
+            "-Zbuild-std-features=compiler-builtins-mem",
+            "--profile",
+            if release { "release" } else { "dev" },
+            "--target",
+            target,
+        ])
+        .arg("--target-dir")
+        .arg(target_dir)
+        .env(
+            "CARGO_ENCODED_RUSTFLAGS",
+            rustflags(&find_rustc_codegen_spirv()?, capabilities),
+        )
+        .current_dir(path_to_crate)
+        .stdout(Stdio::piped());
+
+    if locked {
+        cargo.arg("--locked");
+    }
+
+    let mut child = cargo.spawn()?;
+    let stdout = child.stdout.take().expect("Child stdout is piped");
+
+    let mut counts = DiagnosticCounts::default();
+    let mut diagnostics = vec![];
+    let mut truncated = 0;
+    for line in BufReader::new(stdout).lines() {
+        let Some(diagnostic) = Diagnostic::parse(&line?) else {
+            continue;
+        };
+
+        // JSON output is for tools rather than terminals, so is never truncated
+        let capped = matches!(message_format, MessageFormat::Human)
+            && !diagnostic.is_summary()
+            && max_errors.map_or(false, |max| counts.errors >= max);
+
+        counts.record(&diagnostic);
+        if capped {
+            if diagnostic.level.starts_with("error") {
+                truncated += 1;
+            }
+        } else if filter.accepts(&diagnostic) {
+            diagnostic.emit(message_format);
+        }
+
+        if !diagnostic.is_summary() {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    if truncated > 0 {
+        warn!("... and {truncated} more errors");
+    }
+
+    Ok(CheckReport {
+        passed: child.wait()?.success(),
+        counts,
+        diagnostics,
+    })
+}
@@ -0,0 +1,27 @@
+//! Shell completion scripts, as printed by `--generate-completions`.
+
+use clap::{builder::PossibleValuesParser, Command};
+use clap_complete::Shell;
+
+use crate::target::KNOWN_TARGETS;
+
+/// Levels accepted by `--spirv-metadata`.
+const SPIRV_METADATA: &[&str] = &["none", "name-variables", "full"];
+
+/// Print the completion script for `shell` to stdout.
+///
+/// Arguments parsed by custom value parsers are given their known values as possible values,
+/// so that the script can suggest them. This only affects the generated script, so unlisted
+/// targets are still accepted on the command line.
+pub fn generate(shell: Shell, command: Command) {
+    let mut command = command
+        .mut_arg("targets", |arg| {
+            arg.value_parser(PossibleValuesParser::new(KNOWN_TARGETS))
+        })
+        .mut_arg("spirv_metadata", |arg| {
+            arg.value_parser(PossibleValuesParser::new(SPIRV_METADATA))
+        });
+
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+}
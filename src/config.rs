@@ -0,0 +1,190 @@
+//! Configuration files, as per `--config` and `--dump-config`.
+//!
+//! A config file is a TOML table of settings keyed by flag name, ex. `target = ["vulkan1.2"]`.
+//! Settings are loaded by exporting them as the environment variable of their flag,
+//! so they take precedence over defaults, but not over the environment or the command line.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+
+/// Args that are one-off actions, or detected from the environment, rather than build settings.
+const EXCLUDED: &[&str] = &[
+    "config",
+    "dump_config",
+    "dry_run",
+    "init",
+    "init_name",
+    "init_example",
+    "force",
+    "doctor",
+    "doctor_probe",
+    "list_tools",
+    "clear_cache",
+    "github_actions",
+    "generate_completions",
+];
+
+/// The args of `command` that can be set via a config file.
+fn settings(command: &Command) -> impl Iterator<Item = &Arg> {
+    command.get_arguments().filter(|arg| {
+        !EXCLUDED.contains(&arg.get_id().as_str())
+            && !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version)
+    })
+}
+
+/// The key of an arg in a config file: its flag name, or its ID for positional args.
+fn key(arg: &Arg) -> String {
+    arg.get_long()
+        .map_or_else(|| arg.get_id().as_str().replace('_', "-"), str::to_string)
+}
+
+/// The environment variable an arg is read from.
+///
+/// Args without one of their own, such as --watch-paths, are read from `RUST_GPU_BUILDER_<ID>`.
+fn env_var(arg: &Arg) -> OsString {
+    arg.get_env().map_or_else(
+        || format!("RUST_GPU_BUILDER_{}", arg.get_id().as_str().to_uppercase()).into(),
+        |var| var.to_os_string(),
+    )
+}
+
+/// Find the config file passed via `--config <path>` or `--config=<path>`, falling back to
+/// `RUST_GPU_BUILDER_CONFIG`.
+///
+/// Settings must be loaded before parsing the command line, so this scans it directly.
+pub fn path_from_args() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+
+    std::env::var_os("RUST_GPU_BUILDER_CONFIG").map(PathBuf::from)
+}
+
+/// Load the config file at `path`, exporting each setting as its arg's environment variable
+/// unless that variable is already set.
+pub fn load(command: &Command, path: &Path) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {path:?}: {e:}"))?;
+    let table = contents
+        .parse::<toml::Table>()
+        .map_err(|e| format!("Config file {path:?} isn't valid TOML: {e:}"))?;
+
+    for (key, value) in table {
+        let Some(arg) = settings(command).find(|arg| self::key(arg) == key) else {
+            return Err(format!("Config file {path:?} has unknown setting `{key}`"))
+        };
+
+        let var = env_var(arg);
+        if std::env::var_os(&var).is_some() {
+            continue;
+        }
+
+        let scalar = |value: &toml::Value| match value {
+            toml::Value::String(s) => Ok(s.clone()),
+            toml::Value::Integer(i) => Ok(i.to_string()),
+            toml::Value::Float(f) => Ok(f.to_string()),
+            toml::Value::Boolean(b) => Ok(b.to_string()),
+            _ => Err(format!(
+                "Setting `{key}` in config file {path:?} must be a string, number or boolean"
+            )),
+        };
+
+        let values = match &value {
+            toml::Value::Array(values) => {
+                values.iter().map(scalar).collect::<Result<Vec<_>, _>>()?
+            }
+            value => vec![scalar(value)?],
+        };
+
+        let joined = match (arg.get_value_delimiter(), arg.get_action()) {
+            (Some(delimiter), _) => values.join(&delimiter.to_string()).into(),
+            (None, ArgAction::Append) => std::env::join_paths(&values)
+                .map_err(|e| format!("Invalid path in setting `{key}`: {e:}"))?,
+            (None, _) => match values.as_slice() {
+                [value] => value.into(),
+                _ => {
+                    return Err(format!(
+                        "Setting `{key}` in config file {path:?} takes a single value"
+                    ))
+                }
+            },
+        };
+
+        std::env::set_var(var, joined);
+    }
+
+    Ok(())
+}
+
+/// A raw setting as a TOML value, keeping booleans and integers unquoted.
+fn toml_value(raw: &str) -> toml::Value {
+    match raw {
+        "true" => toml::Value::Boolean(true),
+        "false" => toml::Value::Boolean(false),
+        // Only unquote integers that print back the same, ex. not `007`
+        _ => match raw.parse::<i64>() {
+            Ok(i) if i.to_string() == raw => toml::Value::Integer(i),
+            _ => toml::Value::String(raw.to_string()),
+        },
+    }
+}
+
+/// The effective settings of a parsed command line, after applying defaults and the environment,
+/// as a config file that [`load`]s back to the same settings.
+///
+/// Settings are recorded as given rather than as resolved, ex. `layout` rather than the
+/// layout flags it expands to, so that reloading them resolves the same way.
+/// Settings left at their default are commented out, as setting them explicitly could
+/// conflict with other settings, ex. `release` with `release-on-exit`.
+pub fn dump(command: &Command, matches: &ArgMatches) -> String {
+    let mut out = String::from("# rust-gpu-builder settings, load with --config <path>\n\n");
+    for arg in settings(command) {
+        let id = arg.get_id().as_str();
+        let values = match matches.get_raw(id) {
+            Some(raw) => raw
+                .map(|raw| toml_value(&raw.to_string_lossy()))
+                .collect::<Vec<_>>(),
+            // Args without an environment variable of their own read it manually
+            None if arg.get_env().is_none() => match std::env::var_os(env_var(arg)) {
+                Some(paths) => std::env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .map(|path| toml::Value::String(path.to_string_lossy().into_owned()))
+                    .collect(),
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let value = if matches!(arg.get_action(), ArgAction::Append) {
+            toml::Value::Array(values)
+        } else {
+            match values.into_iter().next() {
+                Some(value) => value,
+                None => continue,
+            }
+        };
+
+        let mut table = toml::Table::new();
+        table.insert(key(arg), value);
+        let line = toml::to_string(&table).expect("Failed to serialize config");
+        if matches.value_source(id) == Some(ValueSource::DefaultValue) {
+            out += "# ";
+        }
+        out += &line;
+    }
+
+    out
+}
@@ -0,0 +1,265 @@
+//! Compiler diagnostics parsed from cargo's JSON messages.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_json::Value;
+
+/// How compiler diagnostics are reported.
+#[derive(Debug, Copy, Clone)]
+pub enum MessageFormat {
+    /// Print rustc's rendered diagnostics.
+    Human,
+    /// Print one JSON object per diagnostic on stdout.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err("Unrecognized message format"),
+        }
+    }
+}
+
+/// Which compiler diagnostics are reported, by severity.
+#[derive(Debug, Copy, Clone)]
+pub enum DiagnosticFilter {
+    /// Report errors only.
+    Error,
+    /// Report errors and warnings.
+    Warning,
+    /// Report every diagnostic, including notes and help.
+    All,
+}
+
+impl DiagnosticFilter {
+    /// Whether `diagnostic` passes this filter.
+    pub fn accepts(&self, diagnostic: &Diagnostic) -> bool {
+        let is_error = diagnostic.level.starts_with("error");
+        match self {
+            DiagnosticFilter::Error => is_error,
+            DiagnosticFilter::Warning => is_error || diagnostic.level == "warning",
+            DiagnosticFilter::All => true,
+        }
+    }
+}
+
+impl FromStr for DiagnosticFilter {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warning" => Ok(Self::Warning),
+            "all" => Ok(Self::All),
+            _ => Err("Unrecognized diagnostic filter"),
+        }
+    }
+}
+
+/// A source location attached to a diagnostic.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub file: String,
+    pub line_start: u64,
+    pub line_end: u64,
+    pub column_start: u64,
+    pub column_end: u64,
+    pub is_primary: bool,
+}
+
+impl Span {
+    /// The path of this span's file.
+    ///
+    /// Resolved against the closest ancestor of `crate_path` containing it,
+    /// then made relative to `GITHUB_WORKSPACE` if inside it.
+    pub fn path(&self, crate_path: &Path) -> PathBuf {
+        let file = crate_path
+            .ancestors()
+            .map(|ancestor| ancestor.join(&self.file))
+            .find(|file| file.exists())
+            .unwrap_or_else(|| crate_path.join(&self.file));
+
+        workspace_relative(file)
+    }
+}
+
+/// Make `path` relative to `GITHUB_WORKSPACE`, if set and containing it.
+pub fn workspace_relative(path: PathBuf) -> PathBuf {
+    if let Some(workspace) = std::env::var_os("GITHUB_WORKSPACE") {
+        if let Ok(relative) = path.strip_prefix(workspace) {
+            return relative.to_path_buf();
+        }
+    }
+
+    path
+}
+
+/// A compiler diagnostic.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Severity, ex. `error` or `warning`.
+    pub level: String,
+    /// Lint or error code, ex. `E0308`.
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<Span>,
+    /// The diagnostic as rendered by rustc.
+    pub rendered: Option<String>,
+}
+
+impl Diagnostic {
+    /// Parse a line of cargo's JSON output.
+    ///
+    /// Returns `None` for malformed lines and messages other than compiler diagnostics,
+    /// so that message kinds added by newer cargo versions are skipped.
+    pub fn parse(line: &str) -> Option<Self> {
+        let message = serde_json::from_str::<Value>(line).ok()?;
+        if message["reason"] != "compiler-message" {
+            return None;
+        }
+
+        let diagnostic = &message["message"];
+        let spans = diagnostic["spans"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|span| {
+                Some(Span {
+                    file: span["file_name"].as_str()?.to_string(),
+                    line_start: span["line_start"].as_u64()?,
+                    line_end: span["line_end"].as_u64()?,
+                    column_start: span["column_start"].as_u64()?,
+                    column_end: span["column_end"].as_u64()?,
+                    is_primary: span["is_primary"].as_bool().unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        Some(Diagnostic {
+            level: diagnostic["level"].as_str()?.to_string(),
+            code: diagnostic["code"]["code"].as_str().map(str::to_string),
+            message: diagnostic["message"].as_str()?.to_string(),
+            spans,
+            rendered: diagnostic["rendered"].as_str().map(str::to_string),
+        })
+    }
+
+    /// Whether this is one of rustc's closing summaries, ex. `aborting due to 2 previous errors`.
+    pub fn is_summary(&self) -> bool {
+        self.spans.is_empty()
+            && (self.message.starts_with("aborting due to")
+                || self.message.ends_with("warning emitted")
+                || self.message.ends_with("warnings emitted"))
+    }
+
+    /// Report this diagnostic in the given format.
+    pub fn emit(&self, format: MessageFormat) {
+        match format {
+            MessageFormat::Human => {
+                if let Some(rendered) = &self.rendered {
+                    eprint!("{rendered}");
+                }
+            }
+            MessageFormat::Json => println!("{}", self.to_json()),
+        }
+    }
+
+    /// Format this diagnostic as a GitHub Actions workflow command, ex. `::error file=..::message`.
+    ///
+    /// Span paths are resolved as per [`Span::path`].
+    pub fn github_annotation(&self, crate_path: &Path) -> Option<String> {
+        let command = match self.level.as_str() {
+            "error" | "error: internal compiler error" => "error",
+            "warning" => "warning",
+            _ => return None,
+        };
+
+        let mut properties = vec![];
+        if let Some(span) = self.spans.iter().find(|span| span.is_primary) {
+            let file = span.path(crate_path);
+            properties.push(format!("file={}", escape_property(&file.to_string_lossy())));
+            properties.push(format!("line={}", span.line_start));
+            properties.push(format!("endLine={}", span.line_end));
+            properties.push(format!("col={}", span.column_start));
+            properties.push(format!("endColumn={}", span.column_end));
+        }
+
+        if let Some(code) = &self.code {
+            properties.push(format!("title={}", escape_property(code)));
+        }
+
+        let properties = if properties.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", properties.join(","))
+        };
+
+        Some(format!(
+            "::{command}{properties}::{}",
+            escape_data(&self.message)
+        ))
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "event": "diagnostic",
+            "level": self.level,
+            "code": self.code,
+            "message": self.message,
+            "spans": self.spans.iter().map(|span| serde_json::json!({
+                "file": span.file,
+                "line_start": span.line_start,
+                "line_end": span.line_end,
+                "column_start": span.column_start,
+                "column_end": span.column_end,
+                "is_primary": span.is_primary,
+            })).collect::<Vec<_>>(),
+            "rendered": self.rendered,
+        })
+    }
+}
+
+/// Escape the message of a GitHub Actions workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value of a GitHub Actions workflow command.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// Error and warning counts for a build.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DiagnosticCounts {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl DiagnosticCounts {
+    /// Count a diagnostic, ignoring rustc's closing summaries.
+    pub fn record(&mut self, diagnostic: &Diagnostic) {
+        if diagnostic.is_summary() {
+            return;
+        }
+
+        match diagnostic.level.as_str() {
+            "error" | "error: internal compiler error" => self.errors += 1,
+            "warning" => self.warnings += 1,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Discovery of shader crates under a directory, as per `--build-all`.
+
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+/// Find every crate under `root`, including `root` itself, that depends on `spirv-std`.
+///
+/// `target` and hidden directories are skipped, and symlinks aren't followed.
+/// Directories whose `Cargo.toml` fails the heuristics are logged at debug level, along with the reason.
+pub fn discover(root: &Path) -> Vec<PathBuf> {
+    let mut crates = vec![];
+    visit(root, &mut crates);
+    crates.sort();
+    crates
+}
+
+fn visit(dir: &Path, crates: &mut Vec<PathBuf>) {
+    let manifest = dir.join("Cargo.toml");
+    if manifest.is_file() {
+        match check_manifest(&manifest) {
+            Ok(()) => crates.push(dir.to_path_buf()),
+            Err(reason) => debug!("Skipping {dir:?}: {reason}"),
+        }
+    }
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return
+    };
+
+    for entry in entries.flatten() {
+        if !entry
+            .file_type()
+            .map_or(false, |file_type| file_type.is_dir())
+        {
+            continue;
+        }
+
+        let name = entry.file_name();
+        if name != "target" && !name.to_string_lossy().starts_with('.') {
+            visit(&entry.path(), crates);
+        }
+    }
+}
+
+/// Check that `manifest` describes a package depending on `spirv-std`, directly or renamed.
+fn check_manifest(manifest: &Path) -> Result<(), String> {
+    let contents =
+        std::fs::read_to_string(manifest).map_err(|e| format!("failed to read Cargo.toml: {e}"))?;
+    let table = contents
+        .parse::<toml::Table>()
+        .map_err(|e| format!("Cargo.toml isn't valid TOML: {e}"))?;
+
+    if !table.get("package").map_or(false, toml::Value::is_table) {
+        return Err("Cargo.toml has no [package] section".to_string());
+    }
+
+    // Dependencies may also be declared per target, ex. `[target.'cfg(target_arch = "spirv")'.dependencies]`
+    let mut dependencies = vec![table.get("dependencies")];
+    if let Some(targets) = table.get("target").and_then(toml::Value::as_table) {
+        dependencies.extend(targets.values().map(|target| target.get("dependencies")));
+    }
+
+    let depends_on_spirv_std = dependencies
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_table)
+        .flatten()
+        .any(|(name, dependency)| {
+            name == "spirv-std"
+                || dependency.get("package").and_then(toml::Value::as_str) == Some("spirv-std")
+        });
+
+    if depends_on_spirv_std {
+        Ok(())
+    } else {
+        Err("Cargo.toml doesn't depend on spirv-std".to_string())
+    }
+}
@@ -0,0 +1,310 @@
+//! Environment diagnostics, as per `--doctor`.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde_json::Value;
+use spirv_builder::{MetadataPrintout, SpirvBuilder};
+use tempfile::TempDir;
+
+use crate::{check, paths};
+
+/// The toolchain file `rustc_codegen_spirv` is pinned to.
+const RUST_TOOLCHAIN: &str = include_str!("../rust-toolchain.toml");
+
+/// Target used to compile the probe crate.
+const PROBE_TARGET: &str = "spirv-unknown-vulkan1.1";
+
+/// Why a check failed, and how to fix it.
+struct Failure {
+    reason: String,
+    fix: String,
+}
+
+impl Failure {
+    fn new(reason: impl Into<String>, fix: impl Into<String>) -> Self {
+        Failure {
+            reason: reason.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+/// Run every check against the shader crate at `path_to_crate`, printing the outcome of each.
+///
+/// If `probe` is set, additionally compile a trivial shader crate.
+/// Returns true if all checks passed.
+pub fn run(path_to_crate: &Path, probe: bool) -> bool {
+    let mut passed = true;
+    let mut report = |name: &str, result: Result<(), Failure>| match result {
+        Ok(()) => println!("[pass] {name}"),
+        Err(failure) => {
+            passed = false;
+            println!("[FAIL] {name}: {}", failure.reason);
+            println!("       fix: {}", failure.fix);
+        }
+    };
+
+    report("Toolchain", check_toolchain());
+
+    let manifest = match check_crate(path_to_crate) {
+        Ok(manifest) => {
+            report("Shader crate", Ok(()));
+            manifest
+        }
+        Err(failure) => {
+            report("Shader crate", Err(failure));
+            return false;
+        }
+    };
+
+    // cargo metadata writes a lockfile if none exists, so look for one beforehand
+    let lockfile = check::find_lockfile(manifest.parent().unwrap_or(&manifest));
+
+    match cargo_metadata(&manifest) {
+        Ok(metadata) => {
+            report("spirv-std dependency", check_spirv_std(&metadata));
+            report("Crate type", check_crate_type(&metadata, &manifest));
+        }
+        Err(failure) => {
+            report("spirv-std dependency", Err(failure));
+        }
+    }
+
+    report(
+        "Cargo.lock",
+        match lockfile {
+            Some(_) => Ok(()),
+            None => Err(Failure::new(
+                "no Cargo.lock found for the shader crate or its workspace",
+                "run `cargo generate-lockfile` in the shader crate, and commit the result",
+            )),
+        },
+    );
+
+    if probe {
+        report("Probe build", check_probe());
+    }
+
+    passed
+}
+
+/// The channel and components of the pinned toolchain.
+fn pinned_toolchain() -> (String, Vec<String>) {
+    let mut channel = String::new();
+    let mut components = vec![];
+    for line in RUST_TOOLCHAIN.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue
+        };
+
+        let value = value.trim();
+        match key.trim() {
+            "channel" => channel = value.trim_matches('"').to_string(),
+            "components" => {
+                components = value
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .split(',')
+                    .map(|component| component.trim().trim_matches('"').to_string())
+                    .filter(|component| !component.is_empty())
+                    .collect()
+            }
+            _ => (),
+        }
+    }
+    (channel, components)
+}
+
+fn check_toolchain() -> Result<(), Failure> {
+    let (channel, components) = pinned_toolchain();
+    let install = format!(
+        "run `rustup toolchain install {channel} --component {}`",
+        components.join(",")
+    );
+
+    let output = Command::new("rustup")
+        .args(["component", "list", "--installed", "--toolchain", &channel])
+        .output()
+        .map_err(|e| {
+            Failure::new(
+                format!("failed to run rustup: {e}"),
+                "install rustup from https://rustup.rs",
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(Failure::new(
+            format!("toolchain {channel} isn't installed"),
+            install,
+        ));
+    }
+
+    // Installed components are listed with their host triple, ex. `rust-src` or `rustc-dev-x86_64-unknown-linux-gnu`
+    let installed = String::from_utf8_lossy(&output.stdout);
+    let missing = components
+        .iter()
+        .filter(|component| {
+            let name = component.trim_end_matches("-preview");
+            !installed
+                .lines()
+                .any(|line| line.trim() == name || line.starts_with(&format!("{name}-")))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::new(
+            format!(
+                "toolchain {channel} is missing components: {}",
+                missing.join(", ")
+            ),
+            format!(
+                "run `rustup component add --toolchain {channel} {}`",
+                missing.join(" ")
+            ),
+        ))
+    }
+}
+
+/// Check that the shader crate exists, returning the path to its manifest.
+fn check_crate(path_to_crate: &Path) -> Result<PathBuf, Failure> {
+    let manifest = if path_to_crate.is_dir() {
+        path_to_crate.join("Cargo.toml")
+    } else {
+        path_to_crate.to_path_buf()
+    };
+
+    if manifest.is_file() && manifest.ends_with("Cargo.toml") {
+        Ok(manifest)
+    } else {
+        Err(Failure::new(
+            format!("{path_to_crate:?} is neither a crate directory nor a Cargo.toml"),
+            "pass the path to the shader crate's directory or its Cargo.toml",
+        ))
+    }
+}
+
+fn cargo_metadata(manifest: &Path) -> Result<Value, Failure> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--manifest-path"])
+        .arg(manifest)
+        .output()
+        .map_err(|e| Failure::new(format!("failed to run cargo: {e}"), "install cargo"))?;
+
+    if !output.status.success() {
+        return Err(Failure::new(
+            format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "fix the errors in the shader crate's Cargo.toml",
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| {
+        Failure::new(
+            format!("failed to parse cargo metadata: {e}"),
+            "update cargo",
+        )
+    })
+}
+
+fn check_spirv_std(metadata: &Value) -> Result<(), Failure> {
+    let packages = metadata["packages"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    if packages
+        .iter()
+        .any(|package| package["name"] == "spirv-std")
+    {
+        Ok(())
+    } else {
+        Err(Failure::new(
+            "spirv-std isn't in the shader crate's dependency tree",
+            "add `spirv-std = \"0.8\"` to the shader crate's [dependencies]",
+        ))
+    }
+}
+
+fn check_crate_type(metadata: &Value, manifest: &Path) -> Result<(), Failure> {
+    let manifest = std::fs::canonicalize(manifest).unwrap_or_else(|_| manifest.to_path_buf());
+    let package = metadata["packages"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .find(|package| {
+            package["manifest_path"]
+                .as_str()
+                .map_or(false, |path| paths::same(Path::new(path), &manifest))
+        });
+
+    let has_dylib = package
+        .and_then(|package| package["targets"].as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|target| target["crate_types"].as_array())
+        .flatten()
+        .any(|crate_type| crate_type == "dylib");
+
+    if has_dylib {
+        Ok(())
+    } else {
+        Err(Failure::new(
+            "the shader crate doesn't build a dylib",
+            "add `crate-type = [\"dylib\"]` to the shader crate's [lib] section",
+        ))
+    }
+}
+
+/// Compile a trivial shader crate, to check the toolchain and codegen backend independently
+/// of the shader crate itself.
+fn check_probe() -> Result<(), Failure> {
+    let dir = TempDir::new().map_err(|e| {
+        Failure::new(
+            format!("failed to create probe crate: {e}"),
+            "check the temporary directory is writable",
+        )
+    })?;
+
+    let write = |path: &str, contents: &str| {
+        std::fs::create_dir_all(dir.path().join(path).parent().unwrap())
+            .and_then(|_| std::fs::write(dir.path().join(path), contents))
+    };
+    write(
+        "Cargo.toml",
+        "[package]\nname = \"probe\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [lib]\ncrate-type = [\"dylib\"]\n\n\
+         [dependencies]\nspirv-std = \"0.8\"\n",
+    )
+    .and_then(|_| {
+        write(
+            "src/lib.rs",
+            "#![no_std]\n\nuse spirv_std::spirv;\n\n#[spirv(fragment)]\npub fn main() {}\n",
+        )
+    })
+    .map_err(|e| {
+        Failure::new(
+            format!("failed to write probe crate: {e}"),
+            "check the temporary directory is writable",
+        )
+    })?;
+
+    SpirvBuilder::new(dir.path(), PROBE_TARGET)
+        .print_metadata(MetadataPrintout::None)
+        .build()
+        .map(drop)
+        .map_err(|e| {
+            Failure::new(
+                format!("failed to compile a trivial shader: {e}"),
+                "check the toolchain above, and that rust-gpu-builder is built with the same spirv-builder version as the shader crate",
+            )
+        })
+}
@@ -0,0 +1,197 @@
+//! Error types.
+
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{Display, Formatter},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use spirv_builder::SpirvBuilderError;
+use tracing::error;
+
+use crate::diagnostics::Diagnostic;
+
+/// Exit code for failures that aren't specific to a build, ex. the watcher giving up.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code for compile errors reported by cargo or the `cargo check` pre-pass.
+pub const EXIT_COMPILE_FAILED: i32 = 2;
+/// Exit code for a shader crate path that doesn't exist.
+pub const EXIT_CRATE_NOT_FOUND: i32 = 3;
+/// Exit code for a missing or malformed `spirv-builder` metadata file.
+pub const EXIT_METADATA: i32 = 4;
+/// Exit code for builder options that `spirv-builder` rejects.
+pub const EXIT_INVALID_OPTIONS: i32 = 5;
+/// Exit code for I/O errors while preparing or checking a build.
+pub const EXIT_IO: i32 = 6;
+/// Exit code for internal errors, ex. a panicked watcher thread.
+pub const EXIT_INTERNAL: i32 = 7;
+
+/// Extract the message from a panic payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Errors that can occur while building a shader crate.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The `cargo check` pre-pass reported errors, along with its diagnostics.
+    CheckFailed(Vec<Diagnostic>),
+    /// The `cargo check` pre-pass could not be run.
+    Check(std::io::Error),
+    /// The temporary target directory for --ephemeral-target could not be created.
+    TargetDir(std::io::Error),
+    /// Free space on the partition containing the target directory is below --critical-free-space-mb,
+    /// along with the target directory and the free space in bytes.
+    DiskSpace(PathBuf, u64),
+    /// `spirv-builder` failed to compile the crate.
+    Spirv(SpirvBuilderError),
+}
+
+/// Classes of build failure that can be retried via `--retries`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RetryClass {
+    /// I/O errors while preparing or checking a build.
+    Io,
+    /// cargo failing after the `cargo check` pre-pass succeeded.
+    Build,
+}
+
+impl FromStr for RetryClass {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "io" => Ok(Self::Io),
+            "build" => Ok(Self::Build),
+            _ => Err("Unrecognized retry class"),
+        }
+    }
+}
+
+impl BuildError {
+    /// Whether this error belongs to one of the given retry classes.
+    ///
+    /// `checked` indicates that the `cargo check` pre-pass succeeded before the build,
+    /// which rules out compile errors as the cause of a cargo failure.
+    pub fn is_transient(&self, classes: &[RetryClass], checked: bool) -> bool {
+        let class = match self {
+            BuildError::Check(_)
+            | BuildError::TargetDir(_)
+            | BuildError::Spirv(SpirvBuilderError::MetadataFileMissing(_)) => RetryClass::Io,
+            BuildError::Spirv(SpirvBuilderError::BuildFailed) if checked => RetryClass::Build,
+            _ => return false,
+        };
+
+        classes.contains(&class)
+    }
+
+    /// The process exit code for a one-shot build that failed with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuildError::CheckFailed(_) => EXIT_COMPILE_FAILED,
+            BuildError::Check(_) | BuildError::TargetDir(_) | BuildError::DiskSpace(..) => EXIT_IO,
+            BuildError::Spirv(e) => match e {
+                SpirvBuilderError::CratePathDoesntExist(_) => EXIT_CRATE_NOT_FOUND,
+                SpirvBuilderError::MetadataFileMissing(_)
+                | SpirvBuilderError::MetadataFileMalformed(_) => EXIT_METADATA,
+                SpirvBuilderError::MultiModuleWithPrintMetadata
+                | SpirvBuilderError::WatchWithPrintMetadata => EXIT_INVALID_OPTIONS,
+                _ => EXIT_COMPILE_FAILED,
+            },
+        }
+    }
+
+    /// Describe this error along with its chain of sources.
+    pub fn chain(&self) -> String {
+        let mut message = self.to_string();
+
+        let mut source = self.source();
+        while let Some(e) = source {
+            message += &format!("\nCaused by: {e:}");
+            source = e.source();
+        }
+
+        message
+    }
+
+    /// Log this error and its chain of sources at error level.
+    pub fn log(&self) {
+        error!("Build failed!\n{}", self.chain());
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::CheckFailed(_) => f.write_str("cargo check failed"),
+            BuildError::Check(_) => f.write_str("failed to run cargo check"),
+            BuildError::TargetDir(_) => f.write_str("failed to create ephemeral target directory"),
+            BuildError::DiskSpace(path, available) => write!(
+                f,
+                "only {} MB free on the partition containing {path:?}",
+                available / (1024 * 1024)
+            ),
+            BuildError::Spirv(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::CheckFailed(_) => None,
+            BuildError::Check(e) => Some(e),
+            BuildError::TargetDir(e) => Some(e),
+            BuildError::DiskSpace(..) => None,
+            BuildError::Spirv(e) => e.source(),
+        }
+    }
+}
+
+/// Errors that stop a file watcher.
+#[derive(Debug)]
+pub enum WatchError {
+    /// The watch path couldn't be resolved, ex. because it no longer exists.
+    Path(PathBuf, std::io::Error),
+    /// The underlying file watcher failed.
+    Notify(PathBuf, notify::Error),
+}
+
+impl WatchError {
+    /// Whether restarting the watcher may resolve this error.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, WatchError::Notify(..))
+    }
+}
+
+impl Display for WatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchError::Path(path, e) => write!(f, "can't watch {path:?}: {e}"),
+            WatchError::Notify(path, e) => write!(f, "watcher for {path:?} failed: {e}"),
+        }
+    }
+}
+
+impl Error for WatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WatchError::Path(_, e) => Some(e),
+            WatchError::Notify(_, e) => Some(e),
+        }
+    }
+}
+
+impl From<SpirvBuilderError> for BuildError {
+    fn from(e: SpirvBuilderError) -> Self {
+        BuildError::Spirv(e)
+    }
+}
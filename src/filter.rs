@@ -0,0 +1,245 @@
+//! Filtering of file watcher events.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use ignore::{gitignore::Gitignore, WalkBuilder};
+
+use tracing::{debug, warn};
+
+use crate::paths;
+
+/// The path representing a burst of changes to the paths in `changed`, which mustn't be empty.
+///
+/// Editors often save by renaming a new file onto the target, ex. VS Code writing a temporary
+/// file first, or by moving the target to a backup before writing it anew, ex. Vim. In both cases
+/// the last changed path that still exists is the saved file, rather than one that was moved away.
+/// Editor temporaries that outlive the save, such as Vim's swap file, are only picked as a last resort.
+pub fn saved_path(changed: &[PathBuf]) -> &PathBuf {
+    let existing = || changed.iter().rev().filter(|path| path.exists());
+    existing()
+        .find(|path| !is_editor_temp(path))
+        .or_else(|| existing().next())
+        .unwrap_or(&changed[0])
+}
+
+/// Whether `path` looks like an editor's temporary file rather than a saved one:
+/// Vim's swap files (ex. `.lib.rs.swp`), backups (ex. `lib.rs~`) and write probes (ex. `4913`).
+fn is_editor_temp(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+        return false
+    };
+
+    name.ends_with('~')
+        || (name.starts_with('.')
+            && [".swp", ".swo", ".swx"]
+                .iter()
+                .any(|ext| name.ends_with(ext)))
+        || name.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Decides which changed paths inside a watched directory should trigger a rebuild.
+#[derive(Debug, Clone, Default)]
+pub struct WatchFilter {
+    /// File extensions that trigger a rebuild, or `None` to accept any file.
+    extensions: Option<Vec<String>>,
+    /// `.gitignore` files whose patterns exclude paths from triggering a rebuild.
+    gitignores: Vec<Gitignore>,
+    /// Files and directories written by the builder itself, which never trigger a rebuild.
+    excluded: Vec<PathBuf>,
+}
+
+impl WatchFilter {
+    /// Create a filter accepting files with any of the given extensions.
+    ///
+    /// An extension of `*` accepts all files.
+    pub fn new(extensions: &[String]) -> Self {
+        let extensions = if extensions.iter().any(|ext| ext == "*") {
+            None
+        } else {
+            Some(
+                extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_string())
+                    .collect(),
+            )
+        };
+
+        WatchFilter {
+            extensions,
+            ..Default::default()
+        }
+    }
+
+    /// Exclude paths matched by the `.gitignore` files that apply to the given watch roots.
+    ///
+    /// This includes `.gitignore` files inside each root, as well as those in its ancestors
+    /// up to the root of the enclosing git repository.
+    pub fn add_gitignores(&mut self, roots: &[PathBuf]) {
+        let mut files = vec![];
+
+        for root in roots.iter().filter(|root| root.is_dir()) {
+            for ancestor in root.ancestors().skip(1) {
+                files.push(ancestor.join(".gitignore"));
+                if ancestor.join(".git").exists() {
+                    break;
+                }
+            }
+
+            let walk = WalkBuilder::new(root)
+                .hidden(false)
+                .filter_entry(|entry| entry.file_name() != ".git")
+                .build();
+
+            files.extend(
+                walk.flatten()
+                    .filter(|entry| entry.file_name() == ".gitignore")
+                    .map(|entry| entry.into_path()),
+            );
+        }
+
+        files.sort();
+        files.dedup();
+
+        for file in files.into_iter().filter(|file| file.is_file()) {
+            let (gitignore, error) = Gitignore::new(&file);
+            if let Some(e) = error {
+                warn!("Failed to parse {file:?}: {e:}");
+            }
+
+            debug!("Applying {file:?} to watched paths");
+            self.gitignores.push(gitignore);
+        }
+    }
+
+    /// Exclude a file written by the builder itself, ex. its log file,
+    /// or a directory and everything in it, ex. --output-dir.
+    pub fn exclude(&mut self, path: PathBuf) {
+        self.excluded.push(paths::normalize(&path));
+    }
+
+    /// Returns true if `path` is excluded by a `.gitignore` file, or lies inside a `.git` directory.
+    fn is_ignored(&self, path: &Path) -> bool {
+        if self.gitignores.is_empty() {
+            return false;
+        }
+
+        path.components()
+            .any(|component| component.as_os_str() == ".git")
+            || self
+                .gitignores
+                .iter()
+                .filter(|gitignore| path.starts_with(gitignore.path()))
+                .any(|gitignore| {
+                    gitignore
+                        .matched_path_or_any_parents(path, path.is_dir())
+                        .is_ignore()
+                })
+    }
+
+    /// Returns true if a change to `path` should trigger a rebuild.
+    pub fn matches(&self, path: &Path) -> bool {
+        if self
+            .excluded
+            .iter()
+            .any(|excluded| paths::inside(path, excluded))
+            || self.is_ignored(path)
+        {
+            return false;
+        }
+
+        let Some(extensions) = &self.extensions else {
+            return true
+        };
+
+        let Some(ext) = path.extension().and_then(OsStr::to_str) else {
+            return false
+        };
+
+        extensions.iter().any(|candidate| {
+            if cfg!(windows) {
+                candidate.eq_ignore_ascii_case(ext)
+            } else {
+                candidate == ext
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create `existing` under a temporary directory, returning it along with a path to each of `names`.
+    fn files(existing: &[&str], names: &[&str]) -> (tempfile::TempDir, Vec<PathBuf>) {
+        let dir = tempfile::tempdir().unwrap();
+        for name in existing {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let paths = names.iter().map(|name| dir.path().join(name)).collect();
+        (dir, paths)
+    }
+
+    #[test]
+    fn editor_temporaries_are_recognized() {
+        for name in [
+            "4913",
+            "5036",
+            "lib.rs~",
+            ".lib.rs.swp",
+            ".lib.rs.swo",
+            ".lib.rs.swx",
+        ] {
+            assert!(is_editor_temp(Path::new(name)), "{name}");
+        }
+        for name in [
+            "lib.rs",
+            "lib.swp",
+            "shader4913.rs",
+            ".gitignore",
+            "Cargo.toml",
+        ] {
+            assert!(!is_editor_temp(Path::new(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn vim_save_picks_the_saved_file() {
+        // Vim probes the directory with `4913`, moves the file to a backup, writes it anew
+        // and deletes the backup, while its swap file lives on
+        let (_dir, changed) = files(
+            &["lib.rs", ".lib.rs.swp"],
+            &[
+                "4913",
+                "4913",
+                "lib.rs",
+                "lib.rs~",
+                "lib.rs",
+                "lib.rs~",
+                ".lib.rs.swp",
+            ],
+        );
+        assert_eq!(saved_path(&changed), &changed[2]);
+    }
+
+    #[test]
+    fn rename_into_place_picks_the_saved_file() {
+        // VS Code and others write a temporary file, then rename it onto the saved one
+        let (_dir, changed) = files(&["lib.rs"], &["lib.rs.tmp", "lib.rs.tmp", "lib.rs"]);
+        assert_eq!(saved_path(&changed), &changed[2]);
+    }
+
+    #[test]
+    fn temporaries_are_picked_as_a_last_resort() {
+        let (_dir, changed) = files(&[".lib.rs.swp"], &["lib.rs", ".lib.rs.swp"]);
+        assert_eq!(saved_path(&changed), &changed[1]);
+    }
+
+    #[test]
+    fn deleted_files_fall_back_to_the_first_change() {
+        let (_dir, changed) = files(&[], &["lib.rs", "other.rs"]);
+        assert_eq!(saved_path(&changed), &changed[0]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Scaffolding of new shader crates, as per `--init`.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use tracing::info;
+
+/// Version of `spirv-std` matching the `spirv-builder` this builder is compiled against.
+///
+/// Must be kept in sync with the `spirv-builder` dependency in Cargo.toml.
+const SPIRV_STD_VERSION: &str = "0.8";
+
+/// Sample entry points for a new shader crate.
+#[derive(Debug, Copy, Clone)]
+pub enum Example {
+    /// A vertex shader drawing a full-screen triangle, and a fragment shader filling it with a solid color.
+    VertexFragment,
+    /// A compute shader doubling each value of a storage buffer.
+    Compute,
+}
+
+impl FromStr for Example {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vertex-fragment" => Ok(Self::VertexFragment),
+            "compute" => Ok(Self::Compute),
+            _ => Err("Unrecognized example"),
+        }
+    }
+}
+
+impl Example {
+    fn lib_rs(self) -> &'static str {
+        match self {
+            Example::VertexFragment => {
+                r#"#![cfg_attr(target_arch = "spirv", no_std)]
+
+use spirv_std::{
+    glam::{vec4, Vec4},
+    spirv,
+};
+
+/// Full-screen triangle, covering the viewport with a single primitive.
+#[spirv(vertex)]
+pub fn main_vs(#[spirv(vertex_index)] vertex_index: i32, #[spirv(position)] out_pos: &mut Vec4) {
+    let x = ((vertex_index << 1) & 2) as f32;
+    let y = (vertex_index & 2) as f32;
+    *out_pos = vec4(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0, 1.0);
+}
+
+#[spirv(fragment)]
+pub fn main_fs(output: &mut Vec4) {
+    *output = vec4(1.0, 0.0, 1.0, 1.0);
+}
+"#
+            }
+            Example::Compute => {
+                r#"#![cfg_attr(target_arch = "spirv", no_std)]
+
+use spirv_std::{glam::UVec3, spirv};
+
+/// Double each value in the storage buffer at binding 0.
+#[spirv(compute(threads(64)))]
+pub fn main_cs(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] values: &mut [f32],
+) {
+    let index = id.x as usize;
+    if index < values.len() {
+        values[index] *= 2.0;
+    }
+}
+"#
+            }
+        }
+    }
+}
+
+/// Why a shader crate couldn't be scaffolded.
+#[derive(Debug)]
+pub enum InitError {
+    /// The crate name isn't a valid cargo package name.
+    InvalidName(String),
+    /// Files that would be overwritten, without `--force`.
+    Exists(Vec<PathBuf>),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitError::InvalidName(name) => write!(
+                f,
+                "{name:?} isn't a valid crate name, pass one containing only letters, digits, `-` and `_` via --init-name"
+            ),
+            InitError::Exists(paths) => write!(
+                f,
+                "{} already exist, pass --force to overwrite them",
+                paths
+                    .iter()
+                    .map(|path| format!("{path:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            InitError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<io::Error> for InitError {
+    fn from(e: io::Error) -> Self {
+        InitError::Io(e)
+    }
+}
+
+/// Write a minimal shader crate into `dir`, named `name` or after `dir` if unset.
+///
+/// Existing files are only overwritten if `force` is set.
+pub fn init(
+    dir: &Path,
+    name: Option<&str>,
+    example: Example,
+    force: bool,
+) -> Result<(), InitError> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => dir
+            .file_name()
+            .map(|name| name.to_string_lossy().replace(['.', ' '], "_"))
+            .unwrap_or_default(),
+    };
+
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(InitError::InvalidName(name));
+    }
+
+    let cargo_toml = format!(
+        r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["dylib"]
+
+[dependencies]
+spirv-std = "{SPIRV_STD_VERSION}"
+
+# Shaders are compiled for a SPIR-V target, so keep the crate out of any enclosing workspace
+[workspace]
+"#
+    );
+
+    let files = [
+        (dir.join("Cargo.toml"), cargo_toml.as_str()),
+        (dir.join("src").join("lib.rs"), example.lib_rs()),
+        (dir.join(".gitignore"), "/target\n"),
+    ];
+
+    let existing = files
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|path| path.exists())
+        .cloned()
+        .collect::<Vec<_>>();
+    if !force && !existing.is_empty() {
+        return Err(InitError::Exists(existing));
+    }
+
+    for (path, contents) in &files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        info!("Wrote {path:?}");
+    }
+
+    Ok(())
+}
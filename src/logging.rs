@@ -0,0 +1,202 @@
+//! Tracing subscriber setup.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use is_terminal::IsTerminal;
+
+use tracing::error;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{filter::LevelFilter, fmt::time::Uptime, prelude::*};
+
+/// Timestamp format for log output.
+#[derive(Debug, Copy, Clone)]
+pub enum LogTime {
+    /// No timestamps.
+    None,
+    /// Time elapsed since the builder started.
+    Uptime,
+    /// Wall-clock time in RFC 3339 format.
+    Rfc3339,
+}
+
+impl FromStr for LogTime {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "uptime" => Ok(Self::Uptime),
+            "rfc3339" => Ok(Self::Rfc3339),
+            _ => Err("Unrecognized log time format"),
+        }
+    }
+}
+
+/// When to use ANSI colors, as per `--color`.
+#[derive(Debug, Copy, Clone)]
+pub enum ColorChoice {
+    /// Use colors if stdout is a terminal, unless overridden by `NO_COLOR` or `CLICOLOR_FORCE`.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether colors are used.
+    ///
+    /// For `auto`, a non-empty `NO_COLOR` disables colors, then a `CLICOLOR_FORCE` other than `0`
+    /// enables them, and otherwise they're used if stdout is a terminal.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                if std::env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty()) {
+                    false
+                } else if std::env::var_os("CLICOLOR_FORCE")
+                    .map_or(false, |value| !value.is_empty() && value != "0")
+                {
+                    true
+                } else {
+                    io::stdout().is_terminal()
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err("Unrecognized color choice"),
+        }
+    }
+}
+
+/// Guard for the Chrome trace writer, held until [`shutdown`] flushes it.
+static CHROME_GUARD: Mutex<Option<FlushGuard>> = Mutex::new(None);
+
+/// Log file written alongside console output, flushed by [`shutdown`].
+static LOG_FILE: Mutex<Option<LineWriter<File>>> = Mutex::new(None);
+
+/// Writer for [`LOG_FILE`], discarding output if the file isn't open.
+struct LogFileWriter;
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A file to duplicate log output to, as per `--log-file`.
+pub struct LogFile<'a> {
+    pub path: &'a Path,
+    /// Most verbose level written to the file, independent of the console.
+    pub level: LevelFilter,
+    /// Append to an existing file instead of truncating it.
+    pub append: bool,
+}
+
+/// Install the global subscriber.
+///
+/// Log output is limited to `INFO` and above, with timestamps in the given format,
+/// and uses ANSI colors if `color` is set.
+/// If `log_file` is set, log output is additionally written to it without ANSI colors,
+/// at its own level. If `trace_file` is set, a Chrome trace including `TRACE`-level spans
+/// and events is additionally written to it.
+pub fn init(log_time: LogTime, color: bool, log_file: Option<LogFile>, trace_file: Option<&Path>) {
+    let chrome = trace_file.map(|path| {
+        let (layer, guard) = ChromeLayerBuilder::new()
+            .file(path)
+            .include_args(true)
+            .build();
+        *CHROME_GUARD.lock().unwrap() = Some(guard);
+        layer
+    });
+
+    let fmt = tracing_subscriber::fmt::layer().with_ansi(color);
+    let fmt = match log_time {
+        LogTime::None => fmt.without_time().boxed(),
+        LogTime::Uptime => fmt.with_timer(Uptime::default()).boxed(),
+        LogTime::Rfc3339 => fmt.boxed(),
+    };
+
+    let log_file = log_file.map(|log_file| {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(log_file.append)
+            .truncate(!log_file.append)
+            .open(log_file.path);
+        (log_file, file)
+    });
+    let mut log_file_error = None;
+    let file_layer = match log_file {
+        Some((log_file, Ok(file))) => {
+            *LOG_FILE.lock().unwrap() = Some(LineWriter::new(file));
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(|| LogFileWriter)
+                    .with_filter(log_file.level),
+            )
+        }
+        Some((log_file, Err(e))) => {
+            log_file_error = Some((log_file.path, e));
+            None
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(LevelFilter::INFO))
+        .with(file_layer)
+        .with(chrome)
+        .init();
+
+    if let Some((path, e)) = log_file_error {
+        error!("Failed to open log file {path:?}: {e:}");
+    }
+}
+
+/// Print a blank line separating sections of log output.
+///
+/// Skipped if stdout isn't a terminal, so that piped output and log files stay compact.
+pub fn separator() {
+    if io::stdout().is_terminal() {
+        println!();
+    }
+}
+
+/// Flush the log file, and flush and close the Chrome trace, if one is being written.
+///
+/// Must be called before exiting, as statics are never dropped.
+pub fn shutdown() {
+    if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+        file.flush().ok();
+    }
+
+    // Dropping the guard flushes the trace and terminates its JSON array
+    drop(CHROME_GUARD.lock().unwrap().take());
+}
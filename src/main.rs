@@ -1,18 +1,67 @@
+mod call_graph;
+mod check;
+mod completions;
+mod config;
+mod diagnostics;
+mod discover;
+mod doctor;
+mod error;
+mod filter;
+mod init;
+mod logging;
+mod metrics;
+mod output;
+mod paths;
+mod preset;
+mod progress;
+mod reflect;
+mod remote;
+mod report;
+mod source_hash;
+mod state;
+mod stats;
+mod target;
+mod template;
+mod tools;
+mod transform;
+
 use std::{
     collections::BTreeMap,
-    error::Error,
+    net::IpAddr,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
+use check::LockfileStatus;
+use diagnostics::{Diagnostic, DiagnosticFilter, MessageFormat};
+use error::{BuildError, RetryClass, WatchError};
+use filter::WatchFilter;
+use logging::{ColorChoice, LogTime};
+use output::{ModuleFormat, ModuleOutput};
+use preset::Preset;
+use progress::Spinner;
+use remote::GitSource;
 use rust_gpu_builder_shared::{RustGpuBuilderModules, RustGpuBuilderOutput};
+use source_hash::SourceCache;
+use state::BuildState;
+use stats::{BuildStats, BuildTimings};
+use tempfile::TempDir;
+use template::OutputTemplate;
 
-use clap::{error::ErrorKind, Parser};
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches,
+    Parser,
+};
+use clap_complete::Shell;
 
 use async_channel::{unbounded, Receiver, Sender};
 use async_executor::Executor;
 use easy_parallel::Parallel;
-use futures_lite::future;
+use fs2::FileExt;
+use futures_lite::{future, FutureExt};
 
 use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
 
@@ -20,7 +69,8 @@ use spirv_builder::{
     Capability, CompileResult, MetadataPrintout, SpirvBuilder, SpirvBuilderError, SpirvMetadata,
 };
 
-use tracing::{error, info};
+use tracing::{debug, error, info, trace, trace_span, warn, Instrument};
+use tracing_subscriber::filter::LevelFilter;
 
 #[derive(Debug, Copy, Clone)]
 pub enum OutputFormat {
@@ -40,68 +90,725 @@ impl FromStr for OutputFormat {
     }
 }
 
+/// Block layout rules to validate buffers against, as per --layout.
+#[derive(Debug, Copy, Clone)]
+pub enum Layout {
+    /// Standard layout rules, with no relaxations.
+    Strict,
+    /// VK_KHR_relaxed_block_layout.
+    Relaxed,
+    /// VK_EXT_scalar_block_layout, also applied to uniform buffers.
+    Scalar,
+    /// No layout validation.
+    Skip,
+}
+
+impl FromStr for Layout {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "relaxed" => Ok(Self::Relaxed),
+            "scalar" => Ok(Self::Scalar),
+            "skip" => Ok(Self::Skip),
+            _ => Err("Unrecognized layout, expected strict, relaxed, scalar or skip"),
+        }
+    }
+}
+
 /// Clap application struct.
 #[derive(Debug, Clone, Parser)]
 #[command(author, version, about, long_about = None)]
 struct ShaderBuilder {
-    /// Shader crate to compile.
+    /// Shader crate to compile, as its directory or the path to its `Cargo.toml`.
+    ///
+    /// May be a glob pattern, ex. `'shaders/*/'`, to build every crate it matches.
+    /// Patterns are expanded by the builder, so should be quoted to keep the shell from expanding them.
+    ///
+    /// May be a `git+<url>[#<rev>]` spec, in which case the repository is cloned into a cache
+    /// under the target directory and built from there.
+    #[arg(
+        required_unless_present_any = ["generate_completions", "list_tools", "dump_config"],
+        env = "RUST_GPU_BUILDER_PATH_TO_CRATE"
+    )]
     path_to_crate: PathBuf,
     /// If set, combined SPIR-V and entrypoint metadata will be written to this file on succesful compile.
+    #[arg(env = "RUST_GPU_BUILDER_OUTPUT_PATH")]
     output_path: Option<PathBuf>,
+    /// Update the cached clone of a `git+` shader crate before building.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_REFRESH")]
+    refresh: bool,
+    /// Treat the crate path as a directory of shader crates, and build every crate found under it
+    /// that depends on `spirv-std`.
+    ///
+    /// Each crate's modules are written to a subdirectory of --output-dir mirroring its path
+    /// under the directory. In watch mode, crates are rediscovered whenever a `Cargo.toml` changes.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["init", "doctor"],
+        env = "RUST_GPU_BUILDER_BUILD_ALL"
+    )]
+    build_all: bool,
+    /// Shader crates found by --build-all or matched by a glob crate path, as set by `refresh_crates`.
+    #[arg(skip)]
+    crates: Vec<PathBuf>,
+    /// Glob pattern the crate path was given as, as set by `resolve_crate_glob`.
+    #[arg(skip)]
+    crate_glob: Option<PathBuf>,
+    /// If set, each compiled SPIR-V module will be written to this directory on succesful compile.
+    #[arg(long, env = "RUST_GPU_BUILDER_OUTPUT_DIR")]
+    output_dir: Option<PathBuf>,
+    /// File extension used for modules written to --output-dir.
+    ///
+    /// May contain additional dots, ex. `vulkan12.spv` produces `<name>.vulkan12.spv`.
+    #[arg(
+        long,
+        value_parser=Self::output_ext,
+        default_value = "spv",
+        env = "RUST_GPU_BUILDER_OUTPUT_EXT"
+    )]
+    output_ext: String,
+    /// The format to write modules to --output-dir in.
+    ///
+    /// `raw` writes SPIR-V binaries, `json` writes entry points and base64-encoded SPIR-V,
+    /// and `embedded` writes Rust source containing the SPIR-V as `pub const` byte arrays.
+    #[arg(long, default_value = "raw", env = "RUST_GPU_BUILDER_MODULE_FORMAT")]
+    module_format: ModuleFormat,
+    /// Filename template for modules written to --output-dir.
+    ///
+    /// Supports `{name}` (crate or entry point name), `{target}` (SPIR-V target),
+    /// `{hash}` / `{hash:N}` (first 8 / N hex characters of the module's SHA-256),
+    /// `{sha256}` (the module's whole SHA-256),
+    /// `{stage}` (ex. `vert`, `frag`, `comp`) and `{ext}` (--output-ext, or `json` / `rs`
+    /// for those module formats).
+    #[arg(
+        long,
+        default_value = "{name}.{ext}",
+        env = "RUST_GPU_BUILDER_OUTPUT_TEMPLATE"
+    )]
+    output_template: OutputTemplate,
+    /// Write modules to --output-dir under content-addressed names, ex. `<name>_<hash>.spv`,
+    /// and atomically point a symlink at the module's usual name to the latest build.
+    ///
+    /// On Windows, a `<name>.spv.latest` file containing the latest file name is written instead.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_CONTENT_ADDRESSED"
+    )]
+    content_addressed: bool,
+    /// Number of content-addressed builds to keep per module, for rollback.
+    #[arg(
+        long,
+        default_value = "3",
+        requires = "content_addressed",
+        env = "RUST_GPU_BUILDER_MAX_KEEP"
+    )]
+    max_keep: usize,
+    /// Archive each build's modules, along with a manifest of their own, into a
+    /// `builds/<build-id>/` subdirectory of --output-dir, keeping this many builds.
+    ///
+    /// `builds/latest` points at the newest build: a symlink on Unix, or a copy on Windows.
+    #[arg(
+        long,
+        requires = "output_dir",
+        conflicts_with = "content_addressed",
+        env = "RUST_GPU_BUILDER_KEEP_BUILDS"
+    )]
+    keep_builds: Option<usize>,
+    /// Disassemble each module with `spirv-dis`, writing a `.spvasm` file into --output-dir,
+    /// or next to the module if no output directory is set.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_EMIT_DISASSEMBLY"
+    )]
+    emit_disassembly: bool,
+    /// Print the struct types of each module, with the offset, array stride and matrix stride of each member.
+    ///
+    /// Printed as a JSON object per module with `--message-format json`.
+    /// Struct and member names are only available with `--spirv-metadata full`.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_PRINT_TYPES")]
+    print_types: bool,
+    /// Print the decorations of each module, such as bindings, locations and built-ins,
+    /// grouped by the variable, type or struct member they apply to.
+    ///
+    /// Printed as a JSON object per module with `--message-format json`.
+    /// Names are only available with `--spirv-metadata name-variables` or `full`.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_PRINT_DECORATIONS"
+    )]
+    print_decorations: bool,
+    /// Print the global variables of each module, such as uniforms, storage buffers, push constants,
+    /// inputs and outputs, with their storage class, type, and binding, location or built-in.
+    ///
+    /// Printed as a JSON object per module with `--message-format json`.
+    /// Names are only available with `--spirv-metadata name-variables` or `full`.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_PRINT_GLOBALS")]
+    print_globals: bool,
+    /// Directory containing the external SPIR-V tools, such as `spirv-dis`.
+    ///
+    /// If unset, tools are looked up via their own environment variable, ex. `SPIRV_DIS`,
+    /// then in `$VULKAN_SDK/bin`, `$HOME/.local/bin` and `PATH`.
+    #[arg(long, env = "RUST_GPU_BUILDER_EXTERNAL_TOOLS_PATH")]
+    external_tools_path: Option<PathBuf>,
+    /// Path to the `spirv-dis` executable, taking precedence over --external-tools-path.
+    #[arg(long, env = "RUST_GPU_BUILDER_SPIRV_DIS_PATH")]
+    spirv_dis_path: Option<PathBuf>,
+    /// Write a Rust source file containing `#[repr(C)]`, `bytemuck`-compatible mirrors of the
+    /// structs used by uniform buffers and push constants.
+    ///
+    /// Member names are only available with `--spirv-metadata full`.
+    #[arg(long, env = "RUST_GPU_BUILDER_BINDINGS_RS_OUT")]
+    bindings_rs_out: Option<PathBuf>,
+    /// Write a DOT graph of the functions in each module and the calls between them,
+    /// with entry points highlighted. Render it with ex. `dot -Tpng`.
+    ///
+    /// Function names are only available with `--spirv-metadata name-variables` or `full`.
+    #[arg(long, env = "RUST_GPU_BUILDER_DEPENDENCY_GRAPH_OUT")]
+    dependency_graph_out: Option<PathBuf>,
+    /// Write a JSON description of each descriptor set binding: its type, count, the entry points
+    /// using it, and the narrowest `stageFlags` covering them, ex. `VK_SHADER_STAGE_ALL_GRAPHICS`
+    /// for a binding shared by vertex and fragment shaders.
+    ///
+    /// Bindings unused by any entry point are only present with --preserve-bindings.
+    #[arg(long, env = "RUST_GPU_BUILDER_DESCRIPTOR_SETS_OUT")]
+    descriptor_sets_out: Option<PathBuf>,
+    /// Write a JSON report of the minimum Vulkan version required by the capabilities the
+    /// compiled shader uses, along with the extensions needed on that version.
+    ///
+    /// Warns if the target is for an earlier Vulkan version.
+    #[arg(long, env = "RUST_GPU_BUILDER_MIN_VULKAN_VERSION_OUT")]
+    min_vulkan_version_out: Option<PathBuf>,
+    /// Strip debug information from written modules, such as names, source and line info.
+    ///
+    /// Applied after compilation, so builds with `--spirv-metadata full` can share a cache
+    /// with stripped release outputs.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_STRIP_DEBUG")]
+    strip_debug: bool,
+    /// Write a JSON manifest listing the execution model (ex. `Vertex`, `Fragment`, `GLCompute`)
+    /// and module of each entry point.
+    #[arg(long, env = "RUST_GPU_BUILDER_MANIFEST")]
+    manifest: Option<PathBuf>,
+    /// Write all modules, along with their manifest, into a single container file.
+    ///
+    /// See the README for the container format.
+    #[arg(long, env = "RUST_GPU_BUILDER_PACK")]
+    pack: Option<PathBuf>,
+    /// Write a JUnit XML report of each build's outcome, for CI systems that display test results.
+    ///
+    /// The crate, and each entry point of a multi-module build, is reported as a test case.
+    /// In watch mode, the report is rewritten after every build.
+    #[arg(long, env = "RUST_GPU_BUILDER_JUNIT_OUT")]
+    junit_out: Option<PathBuf>,
+    /// Write a SARIF 2.1.0 log of each build's diagnostics, for code scanning tools.
+    ///
+    /// Diagnostics are those of the --check-first pre-pass.
+    /// In watch mode, the log is rewritten after every build.
+    #[arg(long, env = "RUST_GPU_BUILDER_SARIF_OUT")]
+    sarif_out: Option<PathBuf>,
+    /// Prepend a string to the name of each entry point in written modules.
+    ///
+    /// Applied after compilation, and reflected in the entry points listed by outputs and
+    /// the manifest. Multi-module builds also key their modules by the renamed entry points.
+    #[arg(long, env = "RUST_GPU_BUILDER_ENTRY_PREFIX")]
+    entry_prefix: Option<String>,
+    /// Append a string to the name of each entry point in written modules, as per --entry-prefix.
+    #[arg(long, env = "RUST_GPU_BUILDER_ENTRY_SUFFIX")]
+    entry_suffix: Option<String>,
+    /// Resolve a relative --output-dir against the shader crate instead of the working directory.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_OUTPUT_RELATIVE_TO_CRATE"
+    )]
+    output_relative_to_crate: bool,
     /// The format to write output in.
-    #[arg(long, default_value = "Messagepack")]
+    #[arg(
+        long,
+        default_value = "Messagepack",
+        env = "RUST_GPU_BUILDER_OUTPUT_FORMAT"
+    )]
     output_format: OutputFormat,
-    /// rust-gpu compile target.
-    #[arg(short, long, default_value = "spirv-unknown-vulkan1.2")]
+    /// rust-gpu compile target, either as a full triple or without the `spirv-unknown-` prefix,
+    /// ex. `vulkan1.2`.
+    ///
+    /// May be repeated to build for several targets, in which case each target's modules
+    /// are written to a subdirectory of --output-dir, unless --output-template contains
+    /// `{target}`.
+    #[arg(
+        short,
+        long = "target",
+        default_value = "spirv-unknown-vulkan1.2",
+        value_parser = target::normalize,
+        value_delimiter = ',',
+        env = "RUST_GPU_BUILDER_TARGET"
+    )]
+    targets: Vec<String>,
+    /// Compile for the given Vulkan version, ex. `1.2` for `spirv-unknown-vulkan1.2`.
+    #[arg(
+        long,
+        value_parser = target::vulkan,
+        conflicts_with = "spirv_version",
+        env = "RUST_GPU_BUILDER_VULKAN"
+    )]
+    vulkan: Option<String>,
+    /// Compile for the given SPIR-V version, ex. `1.5` for `spirv-unknown-spv1.5`.
+    #[arg(long, value_parser = target::spirv, env = "RUST_GPU_BUILDER_SPIRV_VERSION")]
+    spirv_version: Option<String>,
+    /// The target this builder compiles for, as set by `for_target`.
+    #[arg(skip)]
     target: String,
     /// Treat warnings as errors during compilation.
-    #[arg(long, default_value = "false")]
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_DENY_WARNINGS")]
     deny_warnings: bool,
+    /// Require the shader crate's `Cargo.lock` to exist and be up to date, as with cargo's `--locked`.
+    ///
+    /// Without it, a missing or outdated `Cargo.lock` only produces a warning.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_LOCKED")]
+    locked: bool,
     /// Compile shaders in release mode.
-    #[arg(long, default_value = "false")]
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_RELEASE")]
     release: bool,
+    /// In watch mode, build in debug mode for faster rebuilds, then build in release mode
+    /// when exiting via Ctrl-C. One-shot builds are built in release mode directly.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "release",
+        env = "RUST_GPU_BUILDER_RELEASE_ON_EXIT"
+    )]
+    release_on_exit: bool,
     /// Enables the provided SPIR-V capability.
-    #[arg(long, value_parser=Self::spirv_capability)]
+    ///
+    /// Capabilities unsupported by a --target are rejected before building.
+    #[arg(
+        long,
+        value_parser=Self::spirv_capability,
+        value_delimiter = ',',
+        env = "RUST_GPU_BUILDER_CAPABILITY"
+    )]
     capability: Vec<Capability>,
     /// Compile one .spv file per entry point.
-    #[arg(long, default_value = "false")]
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_MULTIMODULE")]
     multimodule: bool,
+    /// Split the compiled module into one module per entry point when writing outputs,
+    /// each containing only the entry point and the functions reachable from it.
+    ///
+    /// Unlike --multimodule, the shader is compiled as a single module, and split afterwards.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "multimodule",
+        env = "RUST_GPU_BUILDER_SPLIT_ENTRY_POINTS"
+    )]
+    split_entry_points: bool,
+    /// Prepend a string to the file name of each module written to --output-dir by
+    /// --multimodule or --split-entry-points builds, ex. `vert_` for `vert_main.spv`.
+    ///
+    /// Unlike --entry-prefix, entry points themselves are left unchanged.
+    #[arg(long, env = "RUST_GPU_BUILDER_MULTIMODULE_PREFIX")]
+    multimodule_prefix: Option<String>,
+    /// Append a string to the file name of each module written to --output-dir by
+    /// --multimodule or --split-entry-points builds, ex. `.vert` for `main.vert.spv`.
+    #[arg(long, env = "RUST_GPU_BUILDER_MULTIMODULE_SUFFIX")]
+    multimodule_suffix: Option<String>,
     /// Set the level of metadata included in the SPIR-V binary.
-    #[arg(long, value_parser=Self::spirv_metadata, default_value = "none")]
+    #[arg(
+        long,
+        value_parser=Self::spirv_metadata,
+        default_value = "none",
+        env = "RUST_GPU_BUILDER_SPIRV_METADATA"
+    )]
     spirv_metadata: SpirvMetadata,
     /// Allow store from one struct type to a different type with compatible layout and members.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_RELAX_STRUCT_STORE"
+    )]
     relax_struct_store: bool,
     /// Allow allocating an object of a pointer type and returning a pointer value from a function
     /// in logical addressing mode.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_RELAX_LOGICAL_POINTER"
+    )]
     relax_logical_pointer: bool,
     /// Enable VK_KHR_relaxed_block_layout when checking standard uniform,
     /// storage buffer, and push constant layouts.
     /// This is the default when targeting Vulkan 1.1 or later.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_RELAX_BLOCK_LAYOUT"
+    )]
     relax_block_layout: bool,
     /// Enable VK_KHR_uniform_buffer_standard_layout when checking standard uniform buffer layouts.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_UNIFORM_BUFFER_STANDARD_LAYOUT"
+    )]
     uniform_buffer_standard_layout: bool,
     /// Enable VK_EXT_scalar_block_layout when checking standard uniform, storage buffer, and push
     /// constant layouts.
     /// Scalar layout rules are more permissive than relaxed block layout so in effect this will
     /// override the --relax-block-layout option.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_SCALAR_BLOCK_LAYOUT"
+    )]
     scalar_block_layout: bool,
     /// Skip checking standard uniform / storage buffer layout. Overrides any --relax-block-layout
     /// or --scalar-block-layout option.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_SKIP_BLOCK_LAYOUT"
+    )]
     skip_block_layout: bool,
+    /// Enable a curated combination of relaxation and layout flags: `strict`, `wgpu` or
+    /// `vulkan-relaxed`.
+    ///
+    /// Flags passed alongside the preset are enabled as well, and --layout takes precedence
+    /// over the preset's layout flags.
+    #[arg(long, env = "RUST_GPU_BUILDER_PRESET")]
+    preset: Option<Preset>,
+    /// Block layout rules to validate buffers against: `strict`, `relaxed`, `scalar` or `skip`.
+    ///
+    /// Sets the combination of layout flags for the given rules, so can't be combined with them.
+    #[arg(
+        long,
+        env = "RUST_GPU_BUILDER_LAYOUT",
+        conflicts_with_all = [
+            "relax_block_layout",
+            "uniform_buffer_standard_layout",
+            "scalar_block_layout",
+            "skip_block_layout",
+        ]
+    )]
+    layout: Option<Layout>,
     /// Preserve unused descriptor bindings. Useful for reflection.
-    #[arg(long, default_value = "false")]
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_PRESERVE_BINDINGS"
+    )]
     preserve_bindings: bool,
+    /// If set, shaders will be compiled into this cargo target directory.
+    ///
+    /// Pointing several builder instances at the same directory lets their shader crates
+    /// share the compiled `spirv-std` dependency stack. Builds are serialized via a lock file
+    /// so that only one instance compiles into the directory at a time.
+    #[arg(long, env = "RUST_GPU_BUILDER_SHARED_TARGET_DIR")]
+    shared_target_dir: Option<PathBuf>,
+    /// Compile each build in a fresh temporary target directory, removed once the build's
+    /// outputs have been written.
+    ///
+    /// Nothing is reused between builds, so every build recompiles the full `spirv-std`
+    /// dependency stack. Modules built by `spirv-builder` are deleted along with the directory,
+    /// so this should be combined with --output-dir or an output path.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "shared_target_dir",
+        env = "RUST_GPU_BUILDER_EPHEMERAL_TARGET"
+    )]
+    ephemeral_target: bool,
+    /// Run `cargo check` on the shader crate before each build, skipping the SPIR-V compile
+    /// if it reports errors.
+    ///
+    /// The check shares the shader build's target directory.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_CHECK_FIRST")]
+    check_first: bool,
+    /// How compiler diagnostics from the --check-first pre-pass are reported.
+    ///
+    /// `human` prints them as rendered by rustc, while `json` prints one JSON object per
+    /// diagnostic on stdout, including its level, code, message and source spans,
+    /// followed by a `summary` event with the session's build statistics on exit.
+    #[arg(long, default_value = "human", env = "RUST_GPU_BUILDER_MESSAGE_FORMAT")]
+    message_format: MessageFormat,
+    /// Which diagnostics from the --check-first pre-pass are reported: `error`, `warning` or `all`.
+    ///
+    /// `warning` reports errors and warnings, while `all` also reports notes and help.
+    /// Filtered diagnostics are still counted, and still reported as GitHub annotations and in SARIF reports.
+    #[arg(long, default_value = "error", env = "RUST_GPU_BUILDER_DIAGNOSTICS")]
+    diagnostics: DiagnosticFilter,
+    /// Stop reporting diagnostics from the --check-first pre-pass after this many errors,
+    /// followed by a count of the errors left out.
+    ///
+    /// Every error is still counted, and still reported as GitHub annotations and in SARIF reports.
+    /// Has no effect with `--message-format json`.
+    #[arg(long, env = "RUST_GPU_BUILDER_MAX_ERRORS")]
+    max_errors: Option<usize>,
+    /// Emit diagnostics from the --check-first pre-pass as GitHub Actions workflow annotations,
+    /// so that they appear in pull request diffs.
+    ///
+    /// Enabled automatically when running in GitHub Actions, as per `GITHUB_ACTIONS`,
+    /// unless `RUST_GPU_BUILDER_GITHUB_ACTIONS` is set.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_GITHUB_ACTIONS")]
+    github_actions: bool,
     /// If set, will watch the provided directory and recompile on change.
     ///
     /// Can be specified multiple times to watch more than one directory.
+    /// Falls back to `RUST_GPU_BUILDER_WATCH_PATHS`, separated like `PATH`.
     #[arg(short, long)]
-    watch_paths: Option<Vec<String>>,
+    watch_paths: Option<Vec<PathBuf>>,
+    /// Additional source directory the shader crate depends on, ex. generated code it `include!`s.
+    ///
+    /// Hashed alongside the crate to detect changes, and watched in watch mode.
+    /// Exposed to the build as `RUST_GPU_BUILDER_INCLUDE_DIRS`, separated like `PATH`.
+    /// Can be specified multiple times. Falls back to `RUST_GPU_BUILDER_INCLUDE_DIRS`.
+    #[arg(long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
+    /// Also watch the shader crate's `build.rs`, if any, in watch mode, and `cargo clean` the crate
+    /// when it changes so that the build script is guaranteed to re-run.
+    ///
+    /// On by default, pass `--rebuild-on-build-script-change false` to disable.
+    #[arg(
+        long,
+        default_value = "true",
+        action = ArgAction::Set,
+        env = "RUST_GPU_BUILDER_REBUILD_ON_BUILD_SCRIPT_CHANGE"
+    )]
+    rebuild_on_build_script_change: bool,
+    /// Comma-separated list of file extensions that trigger a rebuild when changed inside a
+    /// watched directory.
+    ///
+    /// Pass `*` to rebuild on any change. Watched files always trigger a rebuild.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = ["rs", "toml"],
+        env = "RUST_GPU_BUILDER_WATCH_EXTENSIONS"
+    )]
+    watch_extensions: Vec<String>,
+    /// Rebuild on changes to files excluded by `.gitignore`.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_NO_GITIGNORE")]
+    no_gitignore: bool,
+    /// Don't watch the targets of symlinks found inside watched directories.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_NO_FOLLOW_SYMLINKS"
+    )]
+    no_follow_symlinks: bool,
+    /// Delay in milliseconds before restarting a file watcher that returned an error.
+    #[arg(
+        long,
+        default_value = "1000",
+        env = "RUST_GPU_BUILDER_WATCHER_RESTART_DELAY_MS"
+    )]
+    watcher_restart_delay_ms: u64,
+    /// Milliseconds without file events a burst of changes must settle for before rebuilding.
+    ///
+    /// Editors save via several events, ex. renaming a temporary file onto the saved one,
+    /// which are coalesced into a single rebuild.
+    #[arg(long, default_value = "50", env = "RUST_GPU_BUILDER_WATCH_SETTLE_MS")]
+    watch_settle_ms: u64,
+    /// Number of threads driving build and output tasks in watch mode.
+    ///
+    /// This controls the builder's own concurrency, not the parallelism of cargo itself.
+    #[arg(
+        long,
+        default_value = "1",
+        value_parser = clap::value_parser!(u16).range(1..),
+        env = "RUST_GPU_BUILDER_WATCH_THREADS"
+    )]
+    watch_threads: u16,
+    /// Number of consecutive watcher restarts after which the process will exit.
+    #[arg(
+        long,
+        default_value = "5",
+        env = "RUST_GPU_BUILDER_MAX_WATCHER_RESTARTS"
+    )]
+    max_watcher_restarts: usize,
+    /// In watch mode, exit as soon as a build fails, using the same exit code as a failed
+    /// one-shot build.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_EXIT_ON_ERROR")]
+    exit_on_error: bool,
+    /// Number of times to retry a build that fails with a transient error, with exponential
+    /// backoff starting at one second.
+    #[arg(long, default_value = "0", env = "RUST_GPU_BUILDER_RETRIES")]
+    retries: usize,
+    /// Comma-separated classes of failure considered transient by --retries.
+    ///
+    /// `io` covers I/O errors while preparing the build, such as a busy file or missing
+    /// metadata. `build` covers cargo failing after the --check-first pre-pass succeeded,
+    /// and has no effect without it, as compile errors would otherwise be retried too.
+    #[arg(long, value_delimiter = ',', default_values = ["io"], env = "RUST_GPU_BUILDER_RETRY_ON")]
+    retry_on: Vec<RetryClass>,
+    /// If set, print build statistics every N builds in watch mode.
+    ///
+    /// Statistics are always printed when watch mode is exited via Ctrl-C.
+    #[arg(long, env = "RUST_GPU_BUILDER_STATS_INTERVAL")]
+    stats_interval: Option<usize>,
+    /// Serve Prometheus metrics of build outcomes, durations and module sizes on
+    /// `http://<metrics-bind>:<port>/metrics`.
+    #[arg(long, env = "RUST_GPU_BUILDER_METRICS_PORT")]
+    metrics_port: Option<u16>,
+    /// Address to serve --metrics-port on.
+    ///
+    /// Defaults to loopback only, as metrics are unauthenticated and include module
+    /// and entry point names. Pass `0.0.0.0` to serve on every interface.
+    #[arg(
+        long,
+        default_value = "127.0.0.1",
+        env = "RUST_GPU_BUILDER_METRICS_BIND"
+    )]
+    metrics_bind: IpAddr,
+    /// Always rebuild, even if the shader sources and options are unchanged since the last
+    /// successful build.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_NO_SOURCE_CACHE"
+    )]
+    no_source_cache: bool,
+    /// Directory holding the source cache, shared between builder sessions.
+    ///
+    /// Defaults to `~/.cache/rust-gpu-builder` on Linux and `%APPDATA%/rust-gpu-builder` on Windows.
+    #[arg(long, env = "RUST_GPU_BUILDER_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Limit the size of --cache-dir, evicting the least recently used entries beyond it.
+    #[arg(long, env = "RUST_GPU_BUILDER_CACHE_MAX_SIZE_MB")]
+    cache_max_size_mb: Option<u64>,
+    /// Print source cache hits, misses, estimated time saved and size on disk after each build.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_CACHE_STATS")]
+    cache_stats: bool,
+    /// Warn before building if the partition containing the target directory has less free space than this.
+    #[arg(
+        long,
+        default_value = "500",
+        env = "RUST_GPU_BUILDER_MIN_FREE_SPACE_MB"
+    )]
+    min_free_space_mb: u64,
+    /// Fail builds if the partition containing the target directory has less free space than this.
+    #[arg(
+        long,
+        default_value = "100",
+        env = "RUST_GPU_BUILDER_CRITICAL_FREE_SPACE_MB"
+    )]
+    critical_free_space_mb: u64,
+    /// Delete the source cache entries of the shader crate and targets before building.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_CLEAR_CACHE")]
+    clear_cache: bool,
+    /// Always run the initial build, even if the source cache reports the crate as unchanged.
+    ///
+    /// The initial build compiles the crate's dependencies, so that rebuilds in watch mode
+    /// only recompile the shader crate itself. This guarantees that warm-up happens even when
+    /// the dependency cache may have been lost, ex. after `cargo clean` of a shared target dir.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_WARM_UP")]
+    warm_up: bool,
+    /// In watch mode, skip the initial build and start watching straight away,
+    /// building once the first change is detected.
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "warm_up",
+        env = "RUST_GPU_BUILDER_NO_INITIAL_BUILD"
+    )]
+    no_initial_build: bool,
+    /// Persist the outcome of each build to a state file, so that a restarted builder skips
+    /// the initial build and reports the previous outcome if the sources are unchanged.
+    ///
+    /// The state file defaults to `.rust-gpu-builder-state.json` in the shader crate.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_PERSIST_STATE")]
+    persist_state: bool,
+    /// Location of the --persist-state state file.
+    #[arg(long, requires = "persist_state", env = "RUST_GPU_BUILDER_STATE_FILE")]
+    state_file: Option<PathBuf>,
+    /// Timestamp format for log output: `none`, `uptime` (time since startup) or `rfc3339`.
+    #[arg(long, default_value = "rfc3339", env = "RUST_GPU_BUILDER_LOG_TIME")]
+    log_time: LogTime,
+    /// When to use ANSI colors in log output and cargo's output: `auto`, `always` or `never`.
+    ///
+    /// `auto` uses colors if stdout is a terminal. As with cargo, a non-empty `NO_COLOR`
+    /// disables them, and a `CLICOLOR_FORCE` other than `0` enables them.
+    #[arg(long, default_value = "auto", env = "RUST_GPU_BUILDER_COLOR")]
+    color: ColorChoice,
+    /// Duplicate log output to the given file, without ANSI colors.
+    ///
+    /// Changes to the file never trigger a rebuild, even if it's inside a watched directory.
+    #[arg(long, env = "RUST_GPU_BUILDER_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    /// Most verbose level written to --log-file: `error`, `warn`, `info`, `debug` or `trace`.
+    #[arg(long, default_value = "debug", env = "RUST_GPU_BUILDER_LOG_FILE_LEVEL")]
+    log_file_level: LevelFilter,
+    /// Append to an existing --log-file instead of truncating it.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "RUST_GPU_BUILDER_LOG_FILE_APPEND"
+    )]
+    log_file_append: bool,
+    /// Write a Chrome trace of the session to the given file, viewable in `about://tracing`
+    /// or Perfetto.
+    #[arg(long, env = "RUST_GPU_BUILDER_TRACE_FILE")]
+    trace_file: Option<PathBuf>,
+    /// Load settings from a TOML file of flag names and values, as written by --dump-config.
+    ///
+    /// Settings passed on the command line or via environment variables take precedence.
+    #[arg(long, env = "RUST_GPU_BUILDER_CONFIG")]
+    config: Option<PathBuf>,
+    /// Print the effective settings, after applying defaults, environment variables and --config,
+    /// as a --config file and exit.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_DUMP_CONFIG")]
+    dump_config: bool,
+    /// Print the resolved build configuration as JSON and exit without building.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_DRY_RUN")]
+    dry_run: bool,
+    /// Scaffold a new shader crate at the crate path, then exit.
+    ///
+    /// The crate depends on the version of `spirv-std` matching this builder's `spirv-builder`.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_INIT")]
+    init: bool,
+    /// Package name of the crate scaffolded by --init. Defaults to the name of its directory.
+    #[arg(long, requires = "init", env = "RUST_GPU_BUILDER_INIT_NAME")]
+    init_name: Option<String>,
+    /// Sample entry points of the crate scaffolded by --init: `vertex-fragment` or `compute`.
+    #[arg(
+        long,
+        default_value = "vertex-fragment",
+        env = "RUST_GPU_BUILDER_INIT_EXAMPLE"
+    )]
+    init_example: init::Example,
+    /// Overwrite existing files with --init.
+    #[arg(
+        long,
+        default_value = "false",
+        requires = "init",
+        env = "RUST_GPU_BUILDER_FORCE"
+    )]
+    force: bool,
+    /// Diagnose the rust-gpu environment and the shader crate, then exit.
+    ///
+    /// Checks that the pinned toolchain and its components are installed, that the crate exists,
+    /// depends on `spirv-std` and builds a dylib, and that it has a `Cargo.lock`.
+    /// Exits with code 1 if any check fails.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_DOCTOR")]
+    doctor: bool,
+    /// Additionally check that a trivial shader crate compiles with --doctor.
+    #[arg(
+        long,
+        default_value = "false",
+        requires = "doctor",
+        env = "RUST_GPU_BUILDER_DOCTOR_PROBE"
+    )]
+    doctor_probe: bool,
+    /// Print the resolved path of each external SPIR-V tool and exit.
+    #[arg(long, default_value = "false", env = "RUST_GPU_BUILDER_LIST_TOOLS")]
+    list_tools: bool,
+    /// Print a completion script for the given shell and exit.
+    #[arg(long, value_enum, env = "RUST_GPU_BUILDER_GENERATE_COMPLETIONS")]
+    generate_completions: Option<Shell>,
 }
 
 impl ShaderBuilder {
@@ -123,268 +830,2894 @@ impl ShaderBuilder {
         }
     }
 
-    /// Builds a shader with the provided set of options.
-    pub fn build_shader(&self) -> Result<CompileResult, SpirvBuilderError> {
-        // As per `spirv-builder`, apply env vars set in build.rs
-        // to work around potentially suboptimal cargo behaviour
-        std::env::set_var("OUT_DIR", env!("OUT_DIR"));
-        std::env::set_var("PROFILE", env!("PROFILE"));
+    /// Clap value parser for module file extensions.
+    fn output_ext(s: &str) -> Result<String, clap::Error> {
+        let ext = s.trim_start_matches('.');
+        if ext.is_empty() || ext.chars().any(std::path::is_separator) {
+            return Err(clap::Error::new(ErrorKind::InvalidValue));
+        }
+        Ok(ext.to_string())
+    }
 
-        let mut builder = SpirvBuilder::new(&self.path_to_crate, &self.target)
-            .deny_warnings(self.deny_warnings)
-            .release(self.release)
-            .multimodule(self.multimodule)
-            .spirv_metadata(self.spirv_metadata)
-            .relax_struct_store(self.relax_struct_store)
-            .relax_logical_pointer(self.relax_logical_pointer)
-            .relax_block_layout(self.relax_block_layout)
-            .uniform_buffer_standard_layout(self.uniform_buffer_standard_layout)
-            .scalar_block_layout(self.scalar_block_layout)
-            .skip_block_layout(self.skip_block_layout)
-            .preserve_bindings(self.preserve_bindings)
-            .print_metadata(MetadataPrintout::None);
+    /// Read watch paths from `RUST_GPU_BUILDER_WATCH_PATHS` if none were passed.
+    ///
+    /// The variable is split like `PATH` rather than by commas, so that paths may contain them.
+    fn resolve_watch_paths_env(&mut self) {
+        if self.watch_paths.is_some() {
+            return;
+        }
 
-        for capability in &self.capability {
-            builder = builder.capability(*capability);
+        if let Some(paths) = split_paths_env("RUST_GPU_BUILDER_WATCH_PATHS") {
+            self.watch_paths = Some(paths);
         }
+    }
 
-        builder.build()
+    /// Fall back to `GITHUB_ACTIONS`, as set by GitHub Actions runners,
+    /// if neither --github-actions nor `RUST_GPU_BUILDER_GITHUB_ACTIONS` were passed.
+    fn resolve_github_actions(&mut self, matches: &ArgMatches) {
+        if matches.value_source("github_actions") != Some(ValueSource::DefaultValue) {
+            return;
+        }
+
+        self.github_actions =
+            std::env::var("GITHUB_ACTIONS").map_or(false, |value| value == "true");
     }
-}
 
-enum Msg {
-    Change,
-    Build(Result<CompileResult, SpirvBuilderError>),
-}
+    /// Read include directories from `RUST_GPU_BUILDER_INCLUDE_DIRS` if none were passed,
+    /// make them absolute, and export them to the build under the same variable.
+    fn resolve_include_dirs(&mut self) {
+        if self.include_dirs.is_empty() {
+            self.include_dirs =
+                split_paths_env("RUST_GPU_BUILDER_INCLUDE_DIRS").unwrap_or_default();
+        }
 
-/// Instantiate an async watcher and return it alongside a channel to receive events on.
-fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
-    let (tx, rx) = unbounded();
+        if self.include_dirs.is_empty() {
+            return;
+        }
 
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let watcher = RecommendedWatcher::new(
-        move |res| {
-            future::block_on(async {
-                tx.send(res).await.unwrap();
+        let cwd = std::env::current_dir().expect("Failed to get working directory");
+        for dir in &mut self.include_dirs {
+            *dir = cwd.join(&*dir);
+            if !dir.is_dir() {
+                warn!("Include directory {dir:?} doesn't exist");
+            }
+            info!("Including sources from {dir:?}");
+        }
+
+        let dirs = std::env::join_paths(&self.include_dirs).expect("Invalid include directory");
+        std::env::set_var("RUST_GPU_BUILDER_INCLUDE_DIRS", dirs);
+    }
+
+    /// Apply --vulkan or --spirv-version, which must agree with any --target given explicitly,
+    /// and drop duplicate targets.
+    fn resolve_targets(&mut self, matches: &ArgMatches) {
+        if let Some(target) = self.vulkan.clone().or_else(|| self.spirv_version.clone()) {
+            let explicit = matches!(
+                matches.value_source("targets"),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            );
+            if explicit && self.targets.iter().any(|other| *other != target) {
+                ShaderBuilder::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "--target {} disagrees with {target}",
+                            self.targets.join(", ")
+                        ),
+                    )
+                    .exit();
+            }
+            self.targets = vec![target];
+        }
+
+        let mut seen = vec![];
+        self.targets.retain(|target| {
+            let first = !seen.contains(target);
+            seen.push(target.clone());
+            first
+        });
+
+        info!("Compiling for {}", self.targets.join(", "));
+    }
+
+    /// Reject capabilities that are invalid for any of the targets, before building.
+    fn check_capabilities(&self) {
+        let errors = self
+            .targets
+            .iter()
+            .flat_map(|target| {
+                self.capability.iter().filter_map(move |capability| {
+                    target::check_capability(target, &format!("{capability:?}")).err()
+                })
             })
-        },
-        Default::default(),
-    )?;
+            .collect::<Vec<_>>();
 
-    Ok((watcher, rx))
-}
+        if !errors.is_empty() {
+            ShaderBuilder::command()
+                .error(ErrorKind::ArgumentConflict, errors.join("\n"))
+                .exit();
+        }
+    }
 
-/// Watch a file or directory, sending relevant events through the provided channel.
-async fn async_watch<P: AsRef<Path>>(
-    path: P,
-    change_tx: Sender<Msg>,
-) -> Result<(), Box<dyn Error>> {
-    let path = path.as_ref();
-    let path = std::fs::canonicalize(path)
-        .unwrap_or_else(|e| panic!("Failed to canonicalize path {path:?}: {e:}"));
+    /// Enable the flags of --preset.
+    fn resolve_preset(&mut self) {
+        let Some(preset) = self.preset else {
+            return
+        };
 
-    let (mut watcher, rx) = async_watcher()?;
+        self.relax_struct_store |= preset.relax_struct_store;
+        self.relax_logical_pointer |= preset.relax_logical_pointer;
+        self.relax_block_layout |= preset.relax_block_layout;
+        self.uniform_buffer_standard_layout |= preset.uniform_buffer_standard_layout;
+        self.scalar_block_layout |= preset.scalar_block_layout;
+        self.skip_block_layout |= preset.skip_block_layout;
+    }
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    let watch_path = if path.is_dir() {
-        path.clone()
-    } else {
-        path.parent().unwrap().to_owned()
-    };
-    watcher.watch(watch_path.as_ref(), RecursiveMode::Recursive)?;
+    /// Expand --layout into the corresponding layout flags.
+    fn resolve_layout(&mut self) {
+        let Some(layout) = self.layout else {
+            return
+        };
 
-    while let Ok(res) = rx.recv().await {
-        match res {
-            Ok(event) => {
-                if path.is_dir()
-                    || event
-                        .paths
-                        .iter()
-                        .find(|candidate| **candidate == path)
-                        .is_some()
-                {
-                    change_tx.send(Msg::Change).await.unwrap();
+        let (relax, uniform_standard, scalar, skip) = match layout {
+            Layout::Strict => (false, false, false, false),
+            Layout::Relaxed => (true, false, false, false),
+            Layout::Scalar => (true, true, true, false),
+            Layout::Skip => (false, false, false, true),
+        };
+        self.relax_block_layout = relax;
+        self.uniform_buffer_standard_layout = uniform_standard;
+        self.scalar_block_layout = scalar;
+        self.skip_block_layout = skip;
+    }
+
+    /// Replace a `git+` crate path with a local clone, fetching it unless this is a dry run.
+    ///
+    /// Watch paths matching the crate path are replaced too, so the clone is watched instead.
+    fn resolve_git_source(&mut self) {
+        let Some(source) = GitSource::parse(&self.path_to_crate) else {
+            return
+        };
+
+        let dir = if self.dry_run {
+            source.checkout_dir()
+        } else {
+            match source.fetch(self.refresh) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    error!("Failed to fetch shader crate {source:}: {e:}");
+                    logging::shutdown();
+                    std::process::exit(error::EXIT_CRATE_NOT_FOUND);
                 }
             }
-            Err(e) => error!("Watch error: {:?}", e),
+        };
+
+        for path in self.watch_paths.iter_mut().flatten() {
+            if *path == self.path_to_crate {
+                *path = dir.clone();
+            }
         }
+        self.path_to_crate = dir;
     }
 
-    Ok(())
-}
+    /// Accept the shader crate's `Cargo.toml` in place of its directory, as with cargo's `--manifest-path`.
+    ///
+    /// Exits if the crate's manifest fails [`validate_manifest`].
+    /// A dry run only normalizes the path, as a `git+` crate may not have been fetched yet.
+    fn resolve_manifest_path(&mut self) {
+        let path = &self.path_to_crate;
+        let dir = if path.ends_with("Cargo.toml") && !path.is_dir() {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        } else {
+            path.clone()
+        };
 
-async fn handle_compile_result(
-    result: CompileResult,
-    output_path: Option<PathBuf>,
-    output_format: OutputFormat,
-) {
-    info!("Entry Points:");
-    for entry in &result.entry_points {
-        println!("{entry:}");
+        if !self.dry_run {
+            if let Err(message) = validate_manifest(path, &dir) {
+                error!("{message}");
+                logging::shutdown();
+                std::process::exit(error::EXIT_CRATE_NOT_FOUND);
+            }
+        }
+
+        for path in self.watch_paths.iter_mut().flatten() {
+            if *path == self.path_to_crate {
+                *path = dir.clone();
+            }
+        }
+        self.path_to_crate = dir;
     }
 
-    let entry_points = result.entry_points;
+    /// Discover the shader crates under the crate path for --build-all, exiting if there are none.
+    ///
+    /// Unlike a single crate, the crate path itself needn't contain a `Cargo.toml`.
+    fn resolve_build_all(&mut self) {
+        if !self.build_all {
+            return;
+        }
+
+        if !self.path_to_crate.is_dir() {
+            error!(
+                "--build-all requires {:?} to be a directory",
+                self.path_to_crate
+            );
+            logging::shutdown();
+            std::process::exit(error::EXIT_CRATE_NOT_FOUND);
+        }
 
-    println!();
+        if let Err(message) = self.refresh_crates() {
+            error!("{message}");
+            logging::shutdown();
+            std::process::exit(error::EXIT_CRATE_NOT_FOUND);
+        }
+        if self.crates.is_empty() {
+            error!(
+                "Found no crates depending on spirv-std under {:?}, run with RUST_LOG=debug to list the crates that were skipped",
+                self.path_to_crate
+            );
+            logging::shutdown();
+            std::process::exit(error::EXIT_CRATE_NOT_FOUND);
+        }
+    }
 
-    info!("Modules:");
-    match &result.module {
-        spirv_builder::ModuleResult::SingleModule(single) => {
-            println!("{single:?}");
+    /// Expand a crate path containing glob metacharacters into the crates it matches,
+    /// exiting if it matches nothing or any match isn't a crate.
+    ///
+    /// The crate path becomes the pattern's base directory, ex. `shaders` for `shaders/*/`,
+    /// under which each crate's outputs are mirrored as with --build-all.
+    fn resolve_crate_glob(&mut self) {
+        let pattern = self.path_to_crate.clone();
+        self.path_to_crate = paths::glob_base(&pattern);
+        self.crate_glob = Some(pattern.clone());
+
+        if let Err(message) = self.refresh_crates() {
+            error!("{message}");
+            logging::shutdown();
+            std::process::exit(error::EXIT_CRATE_NOT_FOUND);
         }
+        info!("{pattern:?} matches {} crates", self.crates.len());
+    }
 
-        spirv_builder::ModuleResult::MultiModule(multi) => {
-            for (k, module) in multi {
-                println!("{k:}: {module:?}");
-            }
+    /// Refresh the crates found by --build-all or matched by a glob crate path,
+    /// logging any that were added or removed.
+    fn refresh_crates(&mut self) -> Result<(), String> {
+        let crates = match &self.crate_glob {
+            Some(pattern) => expand_crate_glob(pattern)?,
+            None => discover::discover(&self.path_to_crate),
+        };
+        for krate in crates.iter().filter(|krate| !self.crates.contains(krate)) {
+            info!("Found shader crate {krate:?}");
         }
-    };
+        for krate in self.crates.iter().filter(|krate| !crates.contains(krate)) {
+            info!("Shader crate {krate:?} is gone");
+        }
+        self.crates = crates;
+        Ok(())
+    }
 
-    let Some(output_path) = output_path else {
-                                    return
-                                };
+    /// Check the shader crate's `Cargo.lock`, exiting if it's missing or outdated with --locked,
+    /// or warning otherwise.
+    fn check_lockfile(&self) {
+        let message = match check::lockfile_status(&self.path_to_crate) {
+            Some(LockfileStatus::Missing) => format!(
+                "{} has no Cargo.lock, so its dependencies may resolve to versions the pinned toolchain can't build. \
+                 Run `cargo generate-lockfile` in the shader crate and commit the result",
+                self.crate_name()
+            ),
+            Some(LockfileStatus::Outdated) => format!(
+                "The Cargo.lock of {} is out of date, so its dependencies may resolve to versions the pinned toolchain can't build. \
+                 Run `cargo update --workspace` in the shader crate and commit the result",
+                self.crate_name()
+            ),
+            Some(LockfileStatus::UpToDate) | None => return,
+        };
 
-    let modules = match result.module {
-        spirv_builder::ModuleResult::SingleModule(single) => {
-            let module = async_fs::read(single)
-                .await
-                .expect("Failed to read module file");
-            RustGpuBuilderModules::Single(module)
+        if self.locked {
+            error!("{message}");
+            logging::shutdown();
+            std::process::exit(error::EXIT_FAILURE);
         }
+        warn!("{message}");
+        logging::separator();
+    }
 
-        spirv_builder::ModuleResult::MultiModule(multi) => {
-            let mut out = BTreeMap::default();
-            for (k, module) in multi {
-                let module = async_fs::read(module)
-                    .await
-                    .expect("Failed to read module file");
-                out.insert(k, module);
-            }
-            RustGpuBuilderModules::Multi(out)
+    /// Ignore --no-initial-build outside of watch mode, where the initial build is the only one.
+    fn resolve_no_initial_build(&mut self) {
+        if self.no_initial_build && self.watch_paths.is_none() {
+            warn!("--no-initial-build has no effect without --watch-paths");
+            self.no_initial_build = false;
         }
-    };
+    }
 
-    let out = RustGpuBuilderOutput {
-        entry_points,
-        modules,
-    };
+    /// Choose the profile of the session's builds as per --release-on-exit.
+    fn resolve_release_on_exit(&mut self) {
+        if !self.release_on_exit {
+            return;
+        }
 
-    let output_dir = output_path.parent().expect("Output path must be a valid directory");
-    async_fs::create_dir_all(&output_dir).await.ok();
+        if self.watch_paths.is_some() {
+            info!("Watch builds are unoptimized, a release build runs on exit");
+        } else {
+            self.release = true;
+        }
+    }
 
-    match output_format {
-        OutputFormat::Json => {
-            let out = serde_json::to_string_pretty(&out).expect("Failed to serialize output");
+    /// Make a relative --output-dir absolute, resolving it against the working directory
+    /// or the shader crate as per --output-relative-to-crate.
+    fn resolve_output_dir(&mut self) {
+        let Some(output_dir) = &self.output_dir else {
+            return
+        };
 
-            async_fs::write(&output_path, out)
-                .await
-                .expect("Failed to write output");
+        if output_dir.is_relative() {
+            let cwd = std::env::current_dir().expect("Failed to get working directory");
+            let base = if self.output_relative_to_crate {
+                cwd.join(&self.path_to_crate)
+            } else {
+                cwd
+            };
+            self.output_dir = Some(base.join(output_dir));
         }
-        OutputFormat::Messagepack => {
-            let out = rmp_serde::to_vec_named(&out).expect("Failed to serialize output");
-            async_fs::write(&output_path, out)
-                .await
-                .expect("Failed to write output");
+
+        if let Some(output_dir) = &self.output_dir {
+            info!("Writing modules to {output_dir:?}");
         }
     }
-    println!();
-    info!("Wrote output to {output_path:?}");
-}
 
-fn main() {
-    tracing_subscriber::fmt().init();
+    /// Whether more than one --target was given.
+    fn multi_target(&self) -> bool {
+        self.targets.len() > 1
+    }
 
-    let mut args = ShaderBuilder::parse();
+    /// Whether several crates are built, as found by --build-all or matched by a glob crate path.
+    fn multi_crate(&self) -> bool {
+        self.build_all || self.crate_glob.is_some()
+    }
 
-    println!();
-    info!("Shader Builder");
-    println!();
+    /// Whether a session builds more than one crate or target.
+    fn multi_build(&self) -> bool {
+        self.multi_target() || self.multi_crate()
+    }
 
-    info!("Building shader...");
-    println!();
-    match args.build_shader() {
-        Ok(result) => {
-            future::block_on(handle_compile_result(
-                result,
-                args.output_path.clone(),
-                args.output_format,
-            ));
+    /// A builder for each crate and target of the session, in order.
+    fn builders(&self) -> Vec<ShaderBuilder> {
+        let crates = if self.multi_crate() {
+            self.crates.clone()
+        } else {
+            vec![self.path_to_crate.clone()]
+        };
+
+        crates
+            .iter()
+            .flat_map(|krate| {
+                self.targets
+                    .iter()
+                    .map(move |target| self.for_build(krate, target))
+            })
+            .collect()
+    }
+
+    /// This builder, compiling the crate at `path_to_crate` for `target`.
+    ///
+    /// The crate path is only used when building several crates, and must otherwise be that of this builder.
+    fn for_build(&self, path_to_crate: &Path, target: &str) -> ShaderBuilder {
+        if self.multi_crate() {
+            self.for_crate(path_to_crate).for_target(target)
+        } else {
+            self.for_target(target)
         }
-        Err(e) => {
-            error!("Build failed!\n{e:}");
+    }
+
+    /// This builder, compiling one of several crates found by --build-all or matched by a glob crate path.
+    ///
+    /// Per-crate outputs are made distinct: --output-dir gains a subdirectory mirroring the
+    /// crate's path under the root directory, and --output-path, --bindings-rs-out,
+    /// --dependency-graph-out, --descriptor-sets-out, --manifest, --pack, --junit-out, --sarif-out and --state-file
+    /// gain the crate name before their extension.
+    fn for_crate(&self, path_to_crate: &Path) -> ShaderBuilder {
+        let mut builder = self.clone();
+        builder.path_to_crate = path_to_crate.to_path_buf();
+
+        let name = builder.crate_name();
+        let relative = path_to_crate
+            .strip_prefix(&self.path_to_crate)
+            .unwrap_or_else(|_| Path::new(&name));
+        builder.output_dir = self.output_dir.as_ref().map(|dir| dir.join(relative));
+
+        let with_crate = |path: &PathBuf| with_suffix(path, &name);
+        builder.output_path = self.output_path.as_ref().map(with_crate);
+        builder.bindings_rs_out = self.bindings_rs_out.as_ref().map(with_crate);
+        builder.dependency_graph_out = self.dependency_graph_out.as_ref().map(with_crate);
+        builder.descriptor_sets_out = self.descriptor_sets_out.as_ref().map(with_crate);
+        builder.min_vulkan_version_out = self.min_vulkan_version_out.as_ref().map(with_crate);
+        builder.manifest = self.manifest.as_ref().map(with_crate);
+        builder.pack = self.pack.as_ref().map(with_crate);
+        builder.junit_out = self.junit_out.as_ref().map(with_crate);
+        builder.sarif_out = self.sarif_out.as_ref().map(with_crate);
+        builder.state_file = self.state_file.as_ref().map(with_crate);
+
+        // Watch paths covering the crate, such as the root directory itself, would tie
+        // the crate's source hash to changes in its siblings
+        let canonical =
+            |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let crate_path = canonical(path_to_crate);
+        if let Some(watch_paths) = &mut builder.watch_paths {
+            watch_paths.retain(|path| !crate_path.starts_with(canonical(path)));
         }
+
+        builder
     }
-    println!();
 
-    let Some(watch_paths) = args.watch_paths.take() else {
-        return
-    };
+    /// This builder, compiling for a single `target`.
+    ///
+    /// When building for several targets, per-build outputs are made distinct:
+    /// --output-dir gains a subdirectory per target, and --output-path, --bindings-rs-out,
+    /// --dependency-graph-out, --descriptor-sets-out, --min-vulkan-version-out, --pack, --junit-out and --sarif-out
+    /// gain the target before their extension.
+    fn for_target(&self, target: &str) -> ShaderBuilder {
+        let mut builder = self.clone();
+        builder.target = target.to_string();
 
-    let ex = Executor::new();
-    let (change_tx, change_rx) = unbounded::<Msg>();
-    let (build_tx, build_rx) = unbounded::<Msg>();
+        if self.multi_target() {
+            if !self.output_template.uses_target() {
+                builder.output_dir = self.output_dir.as_ref().map(|dir| dir.join(target));
+            }
 
-    Parallel::new()
-        // Spawn file watchers
-        .each(watch_paths, |path| {
-            info!("Watching {path:} for changes...");
-            future::block_on(async {
-                async_watch(path, change_tx)
-                    .await
-                    .expect("Async watcher error");
-            });
-        })
-        // Spawn message Bus
-        .add(|| {
-            let mut building = false;
-            loop {
-                match future::block_on(futures_lite::future::race(
-                    change_rx.recv(),
-                    build_rx.recv(),
-                )) {
-                    // On file change, spawn a build task
-                    Ok(Msg::Change) => {
-                        if !building {
-                            building = true;
-                            println!();
-                            info!("Building shader...");
-                            println!();
-                            ex.spawn({
-                                let build_tx = build_tx.clone();
-                                let args = args.clone();
-                                async move {
-                                    build_tx
-                                        .send(Msg::Build(args.build_shader()))
-                                        .await
-                                        .unwrap();
-                                }
-                            })
-                            .detach();
-                        }
-                    }
-                    // On build complete, spawn a handle_compile_result task
-                    Ok(Msg::Build(result)) => {
-                        if let Ok(result) = result {
-                            let output_path = args.output_path.clone();
-                            let output_format = args.output_format;
-                            ex.spawn(handle_compile_result(result, output_path, output_format))
+            let with_target = |path: &PathBuf| with_suffix(path, target);
+            builder.output_path = self.output_path.as_ref().map(with_target);
+            builder.bindings_rs_out = self.bindings_rs_out.as_ref().map(with_target);
+            builder.dependency_graph_out = self.dependency_graph_out.as_ref().map(with_target);
+            builder.descriptor_sets_out = self.descriptor_sets_out.as_ref().map(with_target);
+            builder.min_vulkan_version_out = self.min_vulkan_version_out.as_ref().map(with_target);
+            builder.junit_out = self.junit_out.as_ref().map(with_target);
+            builder.sarif_out = self.sarif_out.as_ref().map(with_target);
+            builder.pack = self.pack.as_ref().map(with_target);
+        }
+
+        builder
+    }
+
+    /// Whether builds write any files, as opposed to only logging their entry points and modules.
+    fn has_outputs(&self) -> bool {
+        self.output_path.is_some()
+            || self.output_dir.is_some()
+            || self.bindings_rs_out.is_some()
+            || self.dependency_graph_out.is_some()
+            || self.descriptor_sets_out.is_some()
+            || self.min_vulkan_version_out.is_some()
+            || self.manifest.is_some()
+            || self.pack.is_some()
+    }
+
+    /// Whether to show a spinner while building, if the `progress` feature is enabled.
+    ///
+    /// The spinner is drawn on stderr, so is also hidden if stderr isn't a terminal.
+    fn show_progress(&self) -> bool {
+        !matches!(self.message_format, MessageFormat::Json)
+    }
+
+    /// Where external SPIR-V tools are looked up.
+    fn tool_paths(&self) -> tools::ToolPaths {
+        tools::ToolPaths {
+            dir: self.external_tools_path.clone(),
+            spirv_dis: self.spirv_dis_path.clone(),
+        }
+    }
+
+    /// The crate name, qualified with the target when building for several targets.
+    fn display_name(&self) -> String {
+        if self.multi_target() {
+            format!("{} ({})", self.crate_name(), self.target)
+        } else {
+            self.crate_name()
+        }
+    }
+
+    /// Builds a shader with the provided set of options.
+    ///
+    /// If `ephemeral_target` is set, it is used as the cargo target directory.
+    pub fn build_shader(
+        &self,
+        ephemeral_target: Option<&Path>,
+    ) -> Result<CompileResult, SpirvBuilderError> {
+        let _lock = match (ephemeral_target, &self.shared_target_dir) {
+            (Some(target_dir), _) => {
+                std::env::remove_var("OUT_DIR");
+                std::env::set_var("CARGO_TARGET_DIR", target_dir);
+                None
+            }
+            (None, Some(target_dir)) => {
+                // `spirv-builder` only picks its own target directory when OUT_DIR is set,
+                // deferring to cargo (and thus CARGO_TARGET_DIR) otherwise
+                std::env::remove_var("OUT_DIR");
+                std::env::set_var("CARGO_TARGET_DIR", target_dir);
+
+                lock_target_dir(target_dir)
+                    .map_err(|e| warn!("Failed to lock shared target dir {target_dir:?}: {e:}"))
+                    .ok()
+            }
+            (None, None) => {
+                // As per `spirv-builder`, apply env vars set in build.rs
+                // to work around potentially suboptimal cargo behaviour
+                std::env::set_var("OUT_DIR", env!("OUT_DIR"));
+                std::env::set_var("PROFILE", env!("PROFILE"));
+                None
+            }
+        };
+
+        let mut builder = SpirvBuilder::new(&self.path_to_crate, &self.target)
+            .deny_warnings(self.deny_warnings)
+            .release(self.release)
+            .multimodule(self.multimodule)
+            .spirv_metadata(self.spirv_metadata)
+            .relax_struct_store(self.relax_struct_store)
+            .relax_logical_pointer(self.relax_logical_pointer)
+            .relax_block_layout(self.relax_block_layout)
+            .uniform_buffer_standard_layout(self.uniform_buffer_standard_layout)
+            .scalar_block_layout(self.scalar_block_layout)
+            .skip_block_layout(self.skip_block_layout)
+            .preserve_bindings(self.preserve_bindings)
+            .print_metadata(MetadataPrintout::None);
+
+        for capability in &self.capability {
+            builder = builder.capability(*capability);
+        }
+
+        builder.build()
+    }
+
+    /// The resolved build configuration, as printed by --dry-run.
+    fn config(&self) -> serde_json::Value {
+        let path = |path: &Path| path.to_string_lossy().into_owned();
+
+        serde_json::json!({
+            "spirv_builder": {
+                "path_to_crate": path(&self.path_to_crate),
+                "targets": self.targets,
+                "target_dir": path(&self.target_dir()),
+                "release": self.release,
+                "deny_warnings": self.deny_warnings,
+                "multimodule": self.multimodule,
+                "spirv_metadata": format!("{:?}", self.spirv_metadata),
+                "capabilities": self
+                    .capability
+                    .iter()
+                    .map(|capability| format!("{capability:?}"))
+                    .collect::<Vec<_>>(),
+                "relax_struct_store": self.relax_struct_store,
+                "relax_logical_pointer": self.relax_logical_pointer,
+                "relax_block_layout": self.relax_block_layout,
+                "uniform_buffer_standard_layout": self.uniform_buffer_standard_layout,
+                "scalar_block_layout": self.scalar_block_layout,
+                "skip_block_layout": self.skip_block_layout,
+                "layout": self.layout.map(|layout| format!("{layout:?}")),
+                "preset": self.preset.map(|preset| preset.name),
+                "preserve_bindings": self.preserve_bindings,
+            },
+            "crates": self.multi_crate().then(|| {
+                self.crates
+                    .iter()
+                    .map(|krate| path(krate))
+                    .collect::<Vec<_>>()
+            }),
+            "ephemeral_target": self.ephemeral_target,
+            "release_on_exit": self.release_on_exit,
+            "refresh": self.refresh,
+            "check_first": self.check_first,
+            "message_format": format!("{:?}", self.message_format),
+            "diagnostics": format!("{:?}", self.diagnostics),
+            "max_errors": self.max_errors,
+            "github_actions": self.github_actions,
+            "retries": self.retries,
+            "retry_on": format!("{:?}", self.retry_on),
+            "output_path": self.output_path.as_deref().map(path),
+            "output_format": format!("{:?}", self.output_format),
+            "output_dir": self.output_dir.as_deref().map(path),
+            "output_ext": self.output_ext,
+            "module_format": format!("{:?}", self.module_format),
+            "output_template": self.output_template.to_string(),
+            "content_addressed": self.content_addressed.then_some(self.max_keep),
+            "keep_builds": self.keep_builds,
+            "emit_disassembly": self.emit_disassembly,
+            "print_types": self.print_types,
+            "print_decorations": self.print_decorations,
+            "print_globals": self.print_globals,
+            "external_tools_path": self.external_tools_path,
+            "spirv_dis_path": self.spirv_dis_path,
+            "strip_debug": self.strip_debug,
+            "split_entry_points": self.split_entry_points,
+            "multimodule_prefix": self.multimodule_prefix,
+            "multimodule_suffix": self.multimodule_suffix,
+            "entry_prefix": self.entry_prefix,
+            "entry_suffix": self.entry_suffix,
+            "manifest": self.manifest.as_deref().map(path),
+            "pack": self.pack.as_deref().map(path),
+            "junit_out": self.junit_out.as_deref().map(path),
+            "sarif_out": self.sarif_out.as_deref().map(path),
+            "bindings_rs_out": self.bindings_rs_out.as_deref().map(path),
+            "dependency_graph_out": self.dependency_graph_out.as_deref().map(path),
+            "descriptor_sets_out": self.descriptor_sets_out.as_deref().map(path),
+            "min_vulkan_version_out": self.min_vulkan_version_out.as_deref().map(path),
+            "watch_paths": self
+                .watch_paths
+                .iter()
+                .flatten()
+                .map(|watch_path| path(watch_path))
+                .collect::<Vec<_>>(),
+            "include_dirs": self
+                .include_dirs
+                .iter()
+                .map(|include_dir| path(include_dir))
+                .collect::<Vec<_>>(),
+            "watch_extensions": self.watch_extensions,
+            "rebuild_on_build_script_change": self.rebuild_on_build_script_change,
+            "source_cache": !self.no_source_cache,
+            "cache_dir": path(&self.source_cache().dir),
+            "state_file": self.state_path().as_deref().map(path),
+        })
+    }
+
+    /// The build scripts of the shader crates, as watched per --rebuild-on-build-script-change.
+    fn build_scripts(&self) -> Vec<PathBuf> {
+        let mut scripts = vec![];
+        for builder in self.builders() {
+            let script = builder.path_to_crate.join("build.rs");
+            if script.is_file() && !scripts.contains(&script) {
+                scripts.push(script);
+            }
+        }
+        scripts
+    }
+
+    /// If `changed` is this crate's build script, `cargo clean` the crate so the script re-runs,
+    /// as per --rebuild-on-build-script-change.
+    ///
+    /// Failing to clean is only a warning, as cargo re-runs changed build scripts regardless.
+    fn clean_if_build_script(&self, changed: &Path) {
+        let canonical =
+            |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.ephemeral_target
+            || canonical(&self.path_to_crate.join("build.rs")) != canonical(changed)
+        {
+            return;
+        }
+
+        info!("Build script changed, cleaning {}", self.display_name());
+        if let Err(e) = check::cargo_clean(
+            &self.path_to_crate,
+            &self.target_dir(),
+            &self.target,
+            self.release,
+        ) {
+            warn!("Failed to clean {}: {e:}", self.display_name());
+        }
+    }
+
+    /// The cargo target directory shaders are compiled into.
+    fn target_dir(&self) -> PathBuf {
+        match &self.shared_target_dir {
+            Some(target_dir) => target_dir.clone(),
+            // Mirrors `spirv-builder`, which strips `<profile>/build/<crate>/out` from OUT_DIR
+            None => Path::new(env!("OUT_DIR"))
+                .ancestors()
+                .nth(4)
+                .expect("OUT_DIR is not inside a target directory")
+                .join("spirv-builder"),
+        }
+    }
+
+    /// Name of the shader crate's directory, for use in log output.
+    fn crate_name(&self) -> String {
+        std::fs::canonicalize(&self.path_to_crate)
+            .unwrap_or_else(|_| self.path_to_crate.clone())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The source cache, as per --cache-dir and --cache-max-size-mb.
+    fn source_cache(&self) -> SourceCache {
+        SourceCache::new(
+            self.cache_dir
+                .clone()
+                .unwrap_or_else(SourceCache::default_dir),
+            self.cache_max_size_mb.map(|mb| mb * 1024 * 1024),
+        )
+    }
+
+    /// Build options distinguishing source cache entries of the same crate and target:
+    /// those affecting the compiled modules, or how they're transformed before being written.
+    ///
+    /// Options deciding where outputs are written aren't included, as a build only counts as fresh
+    /// while its outputs exist, nor are those only affecting logging or watching.
+    fn source_cache_options(&self) -> String {
+        serde_json::json!({
+            "release": self.release,
+            "deny_warnings": self.deny_warnings,
+            "multimodule": self.multimodule,
+            "spirv_metadata": format!("{:?}", self.spirv_metadata),
+            "capabilities": self
+                .capability
+                .iter()
+                .map(|capability| format!("{capability:?}"))
+                .collect::<Vec<_>>(),
+            "relax_struct_store": self.relax_struct_store,
+            "relax_logical_pointer": self.relax_logical_pointer,
+            "relax_block_layout": self.relax_block_layout,
+            "uniform_buffer_standard_layout": self.uniform_buffer_standard_layout,
+            "scalar_block_layout": self.scalar_block_layout,
+            "skip_block_layout": self.skip_block_layout,
+            "preserve_bindings": self.preserve_bindings,
+            "strip_debug": self.strip_debug,
+            "split_entry_points": self.split_entry_points,
+            "entry_prefix": self.entry_prefix,
+            "entry_suffix": self.entry_suffix,
+            "module_format": format!("{:?}", self.module_format),
+            "output_format": format!("{:?}", self.output_format),
+        })
+        .to_string()
+    }
+
+    /// Key identifying this shader crate and target in the source cache.
+    fn source_cache_key(&self) -> String {
+        let path = std::fs::canonicalize(&self.path_to_crate)
+            .unwrap_or_else(|_| self.path_to_crate.clone());
+        format!("{}#{}", path.to_string_lossy(), self.target)
+    }
+
+    /// Files and directories written by the builder, which never trigger a rebuild.
+    fn written_paths(&self) -> Vec<PathBuf> {
+        let mut written = vec![
+            &self.output_path,
+            &self.output_dir,
+            &self.bindings_rs_out,
+            &self.dependency_graph_out,
+            &self.descriptor_sets_out,
+            &self.min_vulkan_version_out,
+            &self.manifest,
+            &self.pack,
+            &self.junit_out,
+            &self.sarif_out,
+            &self.log_file,
+            &self.trace_file,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+        written.extend(self.state_path());
+        written.push(self.source_cache().dir);
+        written
+    }
+
+    /// Filter for changed or hashed files, as per --watch-extensions, less the builder's own outputs.
+    ///
+    /// `.gitignore` files are applied separately, as they depend on the watched roots.
+    fn watch_filter(&self) -> WatchFilter {
+        let mut filter = WatchFilter::new(&self.watch_extensions);
+        for path in self.written_paths() {
+            filter.exclude(paths::absolute(&path));
+        }
+        filter
+    }
+
+    /// Hash the shader crate, watched paths, include directories and build options.
+    ///
+    /// Only files that would trigger a rebuild in watch mode are hashed, so that outputs
+    /// written inside the crate don't change the hash.
+    /// Returns `None` if the source cache is disabled or hashing failed.
+    fn source_hash(&self) -> Option<String> {
+        if self.no_source_cache {
+            return None;
+        }
+
+        let mut roots = vec![self.path_to_crate.clone()];
+        roots.extend(self.watch_paths.iter().flatten().cloned());
+        roots.extend(self.include_dirs.iter().cloned());
+        // Hashed paths are absolute, so gitignores must be found from absolute roots too
+        let roots = roots
+            .iter()
+            .map(|root| paths::absolute(root))
+            .collect::<Vec<_>>();
+
+        let mut filter = self.watch_filter();
+        if !self.no_gitignore {
+            filter.add_gitignores(&roots);
+        }
+
+        match source_hash::hash_sources(&roots, &self.source_cache_options(), &filter) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("Failed to hash shader sources: {e:}");
+                None
+            }
+        }
+    }
+
+    /// The --persist-state state file, if enabled.
+    fn state_path(&self) -> Option<PathBuf> {
+        if !self.persist_state {
+            return None;
+        }
+
+        Some(
+            self.state_file
+                .clone()
+                .unwrap_or_else(|| self.path_to_crate.join(".rust-gpu-builder-state.json")),
+        )
+    }
+
+    /// The persisted state of the last build, if its sources are unchanged and its outputs
+    /// still exist.
+    fn restore_state(&self) -> Option<BuildState> {
+        let state = BuildState::load(&self.state_path()?, &self.target)?;
+        let hash = self.source_hash()?;
+        state.is_fresh(&hash).then_some(state)
+    }
+
+    /// Persist the outcome of a build, if --persist-state is set.
+    fn save_state(&self, state: BuildState) {
+        let Some(path) = self.state_path() else {
+            return
+        };
+
+        if let Err(e) = state.save(&path, &self.target) {
+            warn!("Failed to persist build state to {path:?}: {e:}");
+        }
+    }
+
+    /// Builds the shader, unless its sources are unchanged since the last successful build
+    /// and `force` is unset.
+    ///
+    /// Returns `None` if the build was skipped.
+    pub fn build_if_changed(&self, force: bool) -> Option<BuildReport> {
+        let _span =
+            trace_span!("build", krate = %self.crate_name(), target = %self.target).entered();
+
+        let source_hash = trace_span!("source_hash").in_scope(|| self.source_hash());
+        if let Some(hash) = source_hash.as_ref().filter(|_| !force) {
+            if self.source_cache().is_fresh(
+                &self.source_cache_key(),
+                &self.source_cache_options(),
+                hash,
+            ) {
+                info!("Sources unchanged, skipping rebuild");
+                return None;
+            }
+        }
+
+        let mut timings = BuildTimings::default();
+
+        let ephemeral_target = if self.ephemeral_target {
+            match tempfile::Builder::new()
+                .prefix("rust-gpu-builder-")
+                .tempdir()
+            {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    return Some(BuildReport {
+                        result: Err(BuildError::TargetDir(e)),
+                        timings,
+                        source_hash,
+                        ephemeral_target: None,
+                        diagnostics: vec![],
+                        path_to_crate: self.path_to_crate.clone(),
+                        target: self.target.clone(),
+                    })
+                }
+            }
+        } else {
+            None
+        };
+        let ephemeral_path = ephemeral_target.as_ref().map(TempDir::path);
+
+        let mut diagnostics = vec![];
+        let mut attempt = 0;
+        let result = loop {
+            match self.check_and_build(ephemeral_path, &mut timings, &mut diagnostics) {
+                Err(e)
+                    if attempt < self.retries
+                        && e.is_transient(&self.retry_on, self.check_first) =>
+                {
+                    attempt += 1;
+                    let delay = Duration::from_millis(500 << attempt.min(6));
+                    warn!(
+                        "Build failed with a transient error, retrying in {delay:.2?} (attempt {attempt:} of {}): {e:}",
+                        self.retries
+                    );
+                    std::thread::sleep(delay);
+                }
+                result => break result,
+            }
+        };
+
+        Some(BuildReport {
+            result,
+            timings,
+            source_hash,
+            ephemeral_target,
+            diagnostics,
+            path_to_crate: self.path_to_crate.clone(),
+            target: self.target.clone(),
+        })
+    }
+
+    /// Warn if free space on the partition containing `target_dir` is below --min-free-space-mb,
+    /// and fail if it's below --critical-free-space-mb.
+    ///
+    /// Free space is measured on the closest existing ancestor, as the target directory is
+    /// created by the build itself.
+    fn check_disk_space(&self, target_dir: &Path) -> Result<(), BuildError> {
+        let Some(dir) = target_dir.ancestors().find(|dir| dir.exists()) else {
+            return Ok(())
+        };
+
+        let available = match fs2::available_space(dir) {
+            Ok(available) => available,
+            Err(e) => {
+                debug!("Failed to measure free space on {dir:?}: {e:}");
+                return Ok(());
+            }
+        };
+
+        let mb = |size: u64| size * 1024 * 1024;
+        if available < mb(self.critical_free_space_mb) {
+            return Err(BuildError::DiskSpace(target_dir.to_path_buf(), available));
+        }
+        if available < mb(self.min_free_space_mb) {
+            warn!(
+                "Only {} MB free on the partition containing {target_dir:?}, the build may run out of space",
+                available / mb(1)
+            );
+        }
+        Ok(())
+    }
+
+    /// Run the optional `cargo check` pre-pass, then compile the shader.
+    fn check_and_build(
+        &self,
+        ephemeral_target: Option<&Path>,
+        timings: &mut BuildTimings,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<CompileResult, BuildError> {
+        diagnostics.clear();
+
+        let target_dir = ephemeral_target
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| self.target_dir());
+        self.check_disk_space(&target_dir)?;
+
+        if self.check_first {
+            let start = Instant::now();
+            let report = trace_span!("check").in_scope(|| {
+                check::cargo_check(
+                    &self.path_to_crate,
+                    &check::CheckOptions {
+                        target_dir: &target_dir,
+                        target: &self.target,
+                        release: self.release,
+                        capabilities: &self.capability,
+                        message_format: self.message_format,
+                        filter: self.diagnostics,
+                        color: self.color.enabled(),
+                        locked: self.locked,
+                        max_errors: self.max_errors,
+                    },
+                )
+            });
+            timings.check = Some(start.elapsed());
+
+            let report = report.map_err(BuildError::Check)?;
+            info!(
+                "cargo check reported {} errors and {} warnings",
+                report.counts.errors, report.counts.warnings
+            );
+
+            if self.github_actions {
+                let crate_path = std::fs::canonicalize(&self.path_to_crate)
+                    .unwrap_or_else(|_| self.path_to_crate.clone());
+                for diagnostic in &report.diagnostics {
+                    if let Some(annotation) = diagnostic.github_annotation(&crate_path) {
+                        println!("{annotation}");
+                    }
+                }
+            }
+
+            if !report.passed {
+                return Err(BuildError::CheckFailed(report.diagnostics));
+            }
+            *diagnostics = report.diagnostics;
+        }
+
+        let start = Instant::now();
+        let result = trace_span!("compile").in_scope(|| self.build_shader(ephemeral_target));
+        timings.compile = start.elapsed();
+
+        result.map_err(BuildError::from)
+    }
+}
+
+/// Write the JUnit report for a build, if requested via --junit-out.
+fn write_junit(args: &ShaderBuilder, report: &BuildReport) {
+    let Some(junit_out) = &args.junit_out else {
+        return
+    };
+
+    let entry_points = report
+        .result
+        .as_ref()
+        .map(|result| result.entry_points.as_slice());
+    let junit = report::junit(
+        &args.crate_name(),
+        entry_points,
+        args.multimodule,
+        report.timings.build(),
+    );
+
+    if let Some(parent) = junit_out.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+
+    match std::fs::write(junit_out, junit) {
+        Ok(()) => info!("Wrote JUnit report to {junit_out:?}"),
+        Err(e) => error!("Failed to write JUnit report to {junit_out:?}: {e:}"),
+    }
+}
+
+/// Write the SARIF log for a build, if requested via --sarif-out.
+fn write_sarif(args: &ShaderBuilder, report: &BuildReport) {
+    let Some(sarif_out) = &args.sarif_out else {
+        return
+    };
+
+    let crate_path =
+        std::fs::canonicalize(&args.path_to_crate).unwrap_or_else(|_| args.path_to_crate.clone());
+    let (diagnostics, failure) = match &report.result {
+        Ok(_) => (report.diagnostics.as_slice(), None),
+        Err(BuildError::CheckFailed(diagnostics)) => (diagnostics.as_slice(), None),
+        Err(e) => (report.diagnostics.as_slice(), Some(e)),
+    };
+    let sarif = report::sarif(&crate_path, diagnostics, failure);
+
+    if let Some(parent) = sarif_out.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+
+    match std::fs::write(sarif_out, sarif) {
+        Ok(()) => info!("Wrote SARIF log to {sarif_out:?}"),
+        Err(e) => error!("Failed to write SARIF log to {sarif_out:?}: {e:}"),
+    }
+}
+
+/// Take an exclusive lock on a shared target directory, blocking until it becomes available.
+///
+/// The lock is released when the returned file is dropped.
+fn lock_target_dir(target_dir: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::create_dir_all(target_dir)?;
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(target_dir.join(".rust-gpu-builder.lock"))?;
+
+    if file.try_lock_exclusive().is_err() {
+        info!("Waiting for lock on shared target dir {target_dir:?}...");
+        file.lock_exclusive()?;
+    }
+
+    Ok(file)
+}
+
+/// The outcome of a single build.
+struct BuildReport {
+    result: Result<CompileResult, BuildError>,
+    timings: BuildTimings,
+    /// Source hash to record in the source cache once outputs have been written.
+    source_hash: Option<String>,
+    /// Temporary target directory for --ephemeral-target, removed when dropped.
+    ephemeral_target: Option<TempDir>,
+    /// Diagnostics from a passing `cargo check` pre-pass.
+    diagnostics: Vec<Diagnostic>,
+    /// The shader crate that was built.
+    path_to_crate: PathBuf,
+    /// The SPIR-V target that was built.
+    target: String,
+}
+
+/// The outcome of building a single crate and target, for the per-build summary.
+#[derive(Debug, Copy, Clone)]
+enum TargetOutcome {
+    Succeeded,
+    Failed,
+    Unchanged,
+}
+
+/// Log the outcome of each crate and target of a multi-target or --build-all build,
+/// keyed by display name.
+fn log_target_outcomes(outcomes: &[(String, TargetOutcome)]) {
+    for (name, outcome) in outcomes {
+        match outcome {
+            TargetOutcome::Succeeded => info!("{name}: succeeded"),
+            TargetOutcome::Failed => error!("{name}: failed"),
+            TargetOutcome::Unchanged => info!("{name}: unchanged"),
+        }
+    }
+}
+
+enum Msg {
+    /// A watched file changed, carrying the path that triggered the change.
+    Change(PathBuf),
+    Build(BuildReport),
+    /// A build was skipped, carrying its display name.
+    Skipped(String),
+    /// A watcher gave up after repeated failures.
+    WatcherFailed(PathBuf),
+    /// A build panicked, carrying its display name, panic message and time spent before panicking.
+    BuildPanicked(String, String, Duration),
+    /// A successful build's outputs have been handled, carrying its display name and whether they were written.
+    Written(String, bool),
+    /// All crates and targets of a build task have been attempted.
+    Finished,
+    /// Ctrl-C was pressed, so exit once the current build and its writes are done.
+    Shutdown,
+}
+
+/// Instantiate an async watcher and return it alongside a channel to receive events on.
+fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
+    let (tx, rx) = unbounded();
+
+    // Automatically select the best implementation for your platform.
+    // You can also access each implementation directly e.g. INotifyWatcher.
+    let watcher = RecommendedWatcher::new(
+        move |res| {
+            // The receiver is dropped when a watcher fails, after which events are moot
+            future::block_on(async {
+                tx.send(res).await.ok();
+            })
+        },
+        Default::default(),
+    )?;
+
+    Ok((watcher, rx))
+}
+
+/// Check that a watch path exists and its contents can be read.
+fn check_watch_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::read_dir(path).map(drop)
+    } else {
+        std::fs::File::open(path).map(drop)
+    }
+}
+
+/// Check that the shader crate at `path`, resolved to the directory `dir`, has a `Cargo.toml`
+/// that parses and contains a `[package]` section, so that a bad crate path fails immediately
+/// rather than deep inside `spirv-builder`.
+fn validate_manifest(path: &Path, dir: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err(format!("Shader crate path {path:?} doesn't exist"));
+    }
+
+    let manifest = dir.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Err(if path.is_dir() {
+            format!("Shader crate directory {path:?} doesn't contain a Cargo.toml")
+        } else {
+            format!("Shader crate path {path:?} is neither a directory nor a Cargo.toml")
+        });
+    }
+
+    let contents = std::fs::read_to_string(&manifest)
+        .map_err(|e| format!("Failed to read {manifest:?}: {e:}"))?;
+    let table = contents
+        .parse::<toml::Table>()
+        .map_err(|e| format!("{manifest:?} isn't valid TOML: {e:}"))?;
+
+    if table.get("package").map_or(false, toml::Value::is_table) {
+        Ok(())
+    } else if table.contains_key("workspace") {
+        Err(format!(
+            "{manifest:?} is a virtual workspace manifest, pass the path to one of its member crates instead"
+        ))
+    } else {
+        Err(format!("{manifest:?} has no [package] section"))
+    }
+}
+
+/// Expand a glob crate path into the crates it matches, checking each with [`validate_manifest`].
+fn expand_crate_glob(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    let crates = paths::expand_glob(pattern)?;
+    for krate in &crates {
+        validate_manifest(krate, krate)
+            .map_err(|message| format!("{message}, as matched by {pattern:?}"))?;
+    }
+    Ok(crates)
+}
+
+/// Insert `suffix` into the file name of `path`, before its extension.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// Read a list of paths from the environment variable `var`, separated like `PATH`.
+fn split_paths_env(var: &str) -> Option<Vec<PathBuf>> {
+    let paths = std::env::split_paths(&std::env::var_os(var)?)
+        .filter(|path| !path.as_os_str().is_empty())
+        .collect::<Vec<_>>();
+    (!paths.is_empty()).then_some(paths)
+}
+
+/// Canonicalize watch paths, dropping duplicates and paths already covered by a watched directory.
+///
+/// Files inside a watched directory are only dropped if the directory's filter accepts them.
+fn dedup_watch_paths(paths: &[PathBuf], filter: &WatchFilter) -> Vec<PathBuf> {
+    let mut canonical = Vec::<PathBuf>::new();
+    for path in paths {
+        let resolved = std::fs::canonicalize(path)
+            .map(|resolved| paths::normalize(&resolved))
+            .unwrap_or_else(|_| path.clone());
+        if canonical.contains(&resolved) {
+            info!("Ignoring duplicate watch path {path:?}");
+        } else {
+            canonical.push(resolved);
+        }
+    }
+    canonical.sort();
+
+    let covering = |path: &Path| {
+        canonical
+            .iter()
+            .find(|other| {
+                *other != path
+                    && other.is_dir()
+                    && path.starts_with(other)
+                    && (path.is_dir() || filter.matches(path))
+            })
+            .cloned()
+    };
+
+    let deduped = canonical
+        .iter()
+        .filter(|path| match covering(path) {
+            Some(other) => {
+                info!("Ignoring watch path {path:?}, as it's covered by {other:?}");
+                false
+            }
+            None => true,
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    debug!("Watch paths: {deduped:?}");
+    deduped
+}
+
+/// Find the targets of all symlinks under `dir`, skipping `target` and hidden directories.
+fn symlink_targets(dir: &Path) -> Vec<PathBuf> {
+    let mut targets = vec![];
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return targets
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue
+        };
+
+        if file_type.is_symlink() {
+            // Dangling links have nothing to watch
+            if let Ok(target) =
+                std::fs::read_link(&path).and_then(|target| std::fs::canonicalize(dir.join(target)))
+            {
+                targets.push(target);
+            }
+        } else if file_type.is_dir() {
+            let name = entry.file_name();
+            if name != "target" && !name.to_string_lossy().starts_with('.') {
+                targets.extend(symlink_targets(&path));
+            }
+        }
+    }
+
+    targets
+}
+
+/// Watch a file or directory, sending relevant events through the provided channel.
+///
+/// `restarts` is reset to zero whenever the watcher successfully delivers an event,
+/// so that it only counts consecutive failures.
+/// Bursts of events are coalesced into a single change once no events arrived for `settle`.
+async fn async_watch<P: AsRef<Path>>(
+    path: P,
+    filter: &WatchFilter,
+    follow_symlinks: bool,
+    settle: Duration,
+    change_tx: Sender<Msg>,
+    restarts: &mut usize,
+) -> Result<(), WatchError> {
+    // Watchers report plain paths, so drop the verbatim prefix canonicalization adds on Windows
+    let path = std::fs::canonicalize(&path)
+        .map(|path| paths::normalize(&path))
+        .map_err(|e| WatchError::Path(path.as_ref().to_owned(), e))?;
+    let notify_error = |e| WatchError::Notify(path.clone(), e);
+
+    let (mut watcher, rx) = async_watcher().map_err(notify_error)?;
+
+    // Add a path to be watched. All files and directories at that path and
+    // below will be monitored for changes.
+    let watch_path = if path.is_dir() {
+        path.clone()
+    } else {
+        path.parent().unwrap().to_owned()
+    };
+    watcher
+        .watch(watch_path.as_ref(), RecursiveMode::Recursive)
+        .map_err(notify_error)?;
+
+    // inotify and friends don't follow symlinks, so watch their targets separately
+    if follow_symlinks && path.is_dir() {
+        for target in symlink_targets(&path) {
+            if target.starts_with(&path) {
+                continue;
+            }
+
+            let mode = if target.is_dir() {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+
+            debug!("Following symlink to {target:?}");
+            watcher.watch(&target, mode).map_err(notify_error)?;
+        }
+    }
+
+    let relevant = |event: &Event| {
+        trace!(kind = ?event.kind, paths = ?event.paths, "File change");
+        event
+            .paths
+            .iter()
+            .filter(|candidate| {
+                if path.is_dir() {
+                    filter.matches(candidate)
+                } else {
+                    paths::same(candidate, &path)
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    while let Ok(res) = rx.recv().await {
+        let event = res.map_err(notify_error)?;
+        *restarts = 0;
+
+        let mut changed = relevant(&event);
+        if changed.is_empty() {
+            continue;
+        }
+
+        // Editors save via a burst of events, ex. Vim moving the file to a backup, writing it anew
+        // and deleting the backup, so wait for the burst to settle to trigger a single rebuild.
+        // Each watcher runs on its own thread, so blocking it is fine
+        let mut events = 1;
+        loop {
+            std::thread::sleep(settle);
+
+            let mut settled = true;
+            while let Ok(res) = rx.try_recv() {
+                let event = res.map_err(notify_error)?;
+                changed.extend(relevant(&event));
+                events += 1;
+                settled = false;
+            }
+
+            if settled {
+                break;
+            }
+        }
+
+        let saved = filter::saved_path(&changed);
+        if events > 1 {
+            debug!("Coalesced {events:} file events into a change to {saved:?}");
+        }
+        change_tx.send(Msg::Change(saved.clone())).await.unwrap();
+    }
+
+    Ok(())
+}
+
+/// Watch `path` via [`async_watch`], restarting it after retryable errors.
+///
+/// Once the watcher can't be restarted, or failed `max_restarts` times in a row,
+/// it gives up and sends a [`Msg::WatcherFailed`].
+async fn supervise_watch(
+    path: &Path,
+    filter: &WatchFilter,
+    follow_symlinks: bool,
+    settle: Duration,
+    restart_delay: Duration,
+    max_restarts: usize,
+    change_tx: Sender<Msg>,
+) {
+    let mut restarts = 0;
+    while let Err(e) = async_watch(
+        path,
+        filter,
+        follow_symlinks,
+        settle,
+        change_tx.clone(),
+        &mut restarts,
+    )
+    .await
+    {
+        error!("Watch error: {e:}");
+        if !e.is_retryable() || restarts >= max_restarts {
+            if e.is_retryable() {
+                error!("Watcher for {path:?} failed {restarts:} times in a row");
+            }
+            change_tx
+                .send(Msg::WatcherFailed(path.to_owned()))
+                .await
+                .unwrap();
+            return;
+        }
+        restarts += 1;
+        std::thread::sleep(restart_delay);
+        info!("Restarting watcher for {path:?} ({restarts:}/{max_restarts:})...");
+    }
+}
+
+/// Write the outputs of a successful build, then report its timings and update the source cache.
+///
+/// `ephemeral_target` is removed once the outputs have been written.
+/// Returns whether the outputs were written, treating a panic while writing as a failure;
+/// if not, the source cache and state are left as they were.
+async fn handle_compile_result(
+    result: CompileResult,
+    args: ShaderBuilder,
+    source_hash: Option<String>,
+    mut timings: BuildTimings,
+    ephemeral_target: Option<TempDir>,
+) -> bool {
+    let mut outputs = build_outputs(&result, &args);
+    // Modules built into an ephemeral target directory are deleted along with it
+    if let Some(target_dir) = &ephemeral_target {
+        outputs.retain(|output| !output.starts_with(target_dir.path()));
+    }
+
+    let start = Instant::now();
+    let write = write_outputs(result, &args).instrument(trace_span!("write"));
+    let written = match AssertUnwindSafe(write).catch_unwind().await {
+        Ok(written) => written,
+        Err(panic) => {
+            error!(
+                "Writing outputs panicked: {}",
+                error::panic_message(&*panic)
+            );
+            None
+        }
+    };
+    timings.write = Some(start.elapsed());
+    drop(ephemeral_target);
+
+    logging::separator();
+    let Some(written) = written else {
+        error!("Failed to write the outputs of {}", args.display_name());
+        return false;
+    };
+    info!("Built {} in {timings:}", args.display_name());
+
+    outputs.extend(written);
+    update_source_cache(&args, source_hash.clone(), &outputs);
+    args.save_state(BuildState::new(source_hash, 0, outputs));
+    true
+}
+
+/// Log that a failed build of `builder` left its previous outputs in place, if it writes any.
+fn log_previous_outputs(builder: &ShaderBuilder, stats: &BuildStats) {
+    if !builder.has_outputs() {
+        return;
+    }
+
+    match stats.last_written.get(&builder.display_name()) {
+        Some(build) => warn!("Keeping previous outputs from build #{build}"),
+        None => warn!("Keeping previous outputs"),
+    }
+}
+
+/// Files written by a build, as recorded by --persist-state: the modules built by
+/// `spirv-builder`, along with the output path, bindings, call graph, descriptor sets,
+/// Vulkan requirements, manifest and pack if set.
+fn build_outputs(result: &CompileResult, args: &ShaderBuilder) -> Vec<PathBuf> {
+    let mut outputs = match &result.module {
+        spirv_builder::ModuleResult::SingleModule(single) => vec![single.clone()],
+        spirv_builder::ModuleResult::MultiModule(multi) => multi.values().cloned().collect(),
+    };
+
+    outputs.extend(
+        [
+            &args.output_path,
+            &args.bindings_rs_out,
+            &args.dependency_graph_out,
+            &args.descriptor_sets_out,
+            &args.min_vulkan_version_out,
+            &args.manifest,
+            &args.pack,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned(),
+    );
+
+    outputs
+}
+
+/// Disassemble each built module into a `.spvasm` file.
+async fn emit_disassembly(
+    module: &spirv_builder::ModuleResult,
+    single_name: &str,
+    args: &ShaderBuilder,
+) {
+    let modules: Vec<(&str, &PathBuf)> = match module {
+        spirv_builder::ModuleResult::SingleModule(single) => vec![(single_name, single)],
+        spirv_builder::ModuleResult::MultiModule(multi) => {
+            multi.iter().map(|(k, v)| (k.as_str(), v)).collect()
+        }
+    };
+
+    if let Some(output_dir) = &args.output_dir {
+        async_fs::create_dir_all(output_dir).await.ok();
+    }
+
+    for (name, path) in modules {
+        let out = match &args.output_dir {
+            Some(output_dir) => output_dir.join(format!("{name}.spvasm")),
+            None => path.with_extension("spvasm"),
+        };
+
+        let path = path.clone();
+        let tools = args.tool_paths();
+        if let Err(e) = blocking::unblock(move || tools::disassemble(&tools, &path, &out)).await {
+            warn!("Failed to disassemble {name}: {e}");
+        }
+    }
+}
+
+/// Print a reflection of each module, as per --print-globals, --print-types and --print-decorations.
+///
+/// `print` renders a named module, and is passed the name of single-module builds as `single_name`.
+fn print_modules(
+    modules: &RustGpuBuilderModules,
+    single_name: &str,
+    print: impl Fn(&str, &[u8]) -> Result<String, rspirv::binary::ParseState>,
+) {
+    let modules: Vec<(&str, &Vec<u8>)> = match modules {
+        RustGpuBuilderModules::Single(module) => vec![(single_name, module)],
+        RustGpuBuilderModules::Multi(multi) => multi.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+    };
+
+    for (name, module) in modules {
+        match print(name, module) {
+            Ok(printed) => println!("{printed}"),
+            Err(e) => error!("Failed to parse module {name}: {e}"),
+        }
+    }
+}
+
+/// Log the entry points and modules of a build, and write them to the configured outputs.
+///
+/// Outputs are staged, and only moved into place once all of them have been generated,
+/// so that a failure leaves the previous outputs untouched. Returns the module files written to
+/// --output-dir, or `None` if the outputs weren't written.
+async fn write_outputs(result: CompileResult, args: &ShaderBuilder) -> Option<Vec<PathBuf>> {
+    info!("Entry Points:");
+    for entry in &result.entry_points {
+        println!("{entry:}");
+    }
+
+    let mut entry_points = result.entry_points;
+
+    logging::separator();
+
+    info!("Modules:");
+    let single_name = match &result.module {
+        spirv_builder::ModuleResult::SingleModule(single) => {
+            println!("{single:?}");
+            let name = output::single_module_name(single);
+            if let Ok(metadata) = std::fs::metadata(single) {
+                metrics::record_module_size(&args.target, &name, metadata.len());
+            }
+            name
+        }
+
+        spirv_builder::ModuleResult::MultiModule(multi) => {
+            for (k, module) in multi {
+                println!("{k:}: {module:?}");
+                if let Ok(metadata) = std::fs::metadata(module) {
+                    metrics::record_module_size(&args.target, k, metadata.len());
+                }
+            }
+            String::default()
+        }
+    };
+
+    // Disassembly is written to the output directory too, so check it first
+    if let Some(output_dir) = &args.output_dir {
+        if !output::prepare_output_dir(output_dir).await {
+            return None;
+        }
+    }
+
+    if args.emit_disassembly {
+        logging::separator();
+        emit_disassembly(&result.module, &single_name, args).await;
+    }
+
+    if !args.has_outputs() && !args.print_types && !args.print_decorations && !args.print_globals {
+        return Some(vec![]);
+    }
+
+    let mut modules = match result.module {
+        spirv_builder::ModuleResult::SingleModule(single) => {
+            let module = async_fs::read(single)
+                .await
+                .expect("Failed to read module file");
+            RustGpuBuilderModules::Single(module)
+        }
+
+        spirv_builder::ModuleResult::MultiModule(multi) => {
+            let mut out = BTreeMap::default();
+            for (k, module) in multi {
+                let module = async_fs::read(module)
+                    .await
+                    .expect("Failed to read module file");
+                out.insert(k, module);
+            }
+            RustGpuBuilderModules::Multi(out)
+        }
+    };
+
+    // Print globals, types and decorations before stripping, as they rely on debug names
+    if args.print_globals {
+        logging::separator();
+        info!("Globals:");
+        print_modules(&modules, &single_name, |name, module| {
+            match args.message_format {
+                MessageFormat::Human => {
+                    reflect::globals_text(module).map(|globals| format!("{name}:\n{globals}"))
+                }
+                MessageFormat::Json => reflect::globals_json(module).map(|globals| {
+                    serde_json::json!({ "event": "globals", "module": name, "globals": globals })
+                        .to_string()
+                }),
+            }
+        });
+    }
+
+    if args.print_types {
+        logging::separator();
+        info!("Types:");
+        print_modules(&modules, &single_name, |name, module| {
+            match args.message_format {
+                MessageFormat::Human => {
+                    reflect::types_text(module).map(|types| format!("{name}:\n{types}"))
+                }
+                MessageFormat::Json => reflect::types_json(module).map(|types| {
+                    serde_json::json!({ "event": "types", "module": name, "structs": types })
+                        .to_string()
+                }),
+            }
+        });
+    }
+
+    if args.print_decorations {
+        logging::separator();
+        info!("Decorations:");
+        print_modules(&modules, &single_name, |name, module| {
+            match args.message_format {
+                MessageFormat::Human => reflect::decorations_text(module)
+                    .map(|decorations| format!("{name}:\n{decorations}")),
+                MessageFormat::Json => reflect::decorations_json(module).map(|decorations| {
+                    serde_json::json!({ "event": "decorations", "module": name, "decorations": decorations })
+                        .to_string()
+                }),
+            }
+        });
+    }
+
+    let mut staging = output::Staging::default();
+
+    // Likewise for bindings
+    if let Some(bindings_rs_out) = &args.bindings_rs_out {
+        logging::separator();
+        if !output::write_bindings(&modules, bindings_rs_out, &mut staging).await {
+            return None;
+        }
+    }
+
+    // Likewise for the call graph, which is labelled with function names
+    if let Some(dependency_graph_out) = &args.dependency_graph_out {
+        logging::separator();
+        if !output::write_call_graph(&modules, &single_name, dependency_graph_out, &mut staging)
+            .await
+        {
+            return None;
+        }
+    }
+
+    if let Some(descriptor_sets_out) = &args.descriptor_sets_out {
+        logging::separator();
+        if !output::write_descriptor_sets(&modules, descriptor_sets_out, &mut staging).await {
+            return None;
+        }
+    }
+
+    if let Some(min_vulkan_version_out) = &args.min_vulkan_version_out {
+        logging::separator();
+        if !output::write_min_vulkan_version(
+            &modules,
+            &args.target,
+            min_vulkan_version_out,
+            &mut staging,
+        )
+        .await
+        {
+            return None;
+        }
+    }
+
+    if args.strip_debug {
+        if let Err(e) = transform::apply(&mut modules, transform::strip_debug) {
+            error!("Failed to strip debug info: {e}");
+            return None;
+        }
+    }
+
+    if args.split_entry_points {
+        if let RustGpuBuilderModules::Single(module) = &modules {
+            match transform::split_entry_points(module) {
+                Ok(split) => modules = RustGpuBuilderModules::Multi(split),
+                Err(e) => {
+                    error!("Failed to split entry points: {e}");
+                    return None;
+                }
+            }
+        }
+    }
+
+    if args.entry_prefix.is_some() || args.entry_suffix.is_some() {
+        let prefix = args.entry_prefix.as_deref().unwrap_or_default();
+        let suffix = args.entry_suffix.as_deref().unwrap_or_default();
+        let rename = |name: &String| format!("{prefix}{name}{suffix}");
+
+        if let Err(e) = transform::apply(&mut modules, |module| {
+            transform::rename_entry_points(module, prefix, suffix)
+        }) {
+            error!("Failed to rename entry points: {e}");
+            return None;
+        }
+
+        entry_points = entry_points.iter().map(rename).collect();
+        if let RustGpuBuilderModules::Multi(multi) = &mut modules {
+            *multi = std::mem::take(multi)
+                .into_iter()
+                .map(|(k, module)| (rename(&k), module))
+                .collect();
+        }
+    }
+
+    let mut written = vec![];
+    let archive = args
+        .output_dir
+        .as_deref()
+        .filter(|_| args.keep_builds.is_some())
+        .map(output::build_archive_dir);
+    if let Some(output_dir) = &args.output_dir {
+        logging::separator();
+        let staged = output::write_output(
+            &modules,
+            &entry_points,
+            &single_name,
+            &ModuleOutput {
+                dir: output_dir,
+                format: args.module_format,
+                template: &args.output_template,
+                ext: &args.output_ext,
+                target: &args.target,
+                content_addressed: args.content_addressed.then_some(args.max_keep),
+                multimodule_prefix: args.multimodule_prefix.as_deref().unwrap_or_default(),
+                multimodule_suffix: args.multimodule_suffix.as_deref().unwrap_or_default(),
+                archive: archive.as_deref(),
+            },
+            &mut staging,
+        )
+        .await;
+        match staged {
+            Some(staged) => written = staged,
+            None => return None,
+        }
+    }
+
+    if let Some(manifest) = &args.manifest {
+        logging::separator();
+        if !output::write_manifest(
+            &modules,
+            &single_name,
+            &written,
+            &args.target,
+            args.multi_target(),
+            manifest,
+            &mut staging,
+        )
+        .await
+        {
+            return None;
+        }
+    }
+
+    // Archived builds carry a single-target manifest of their own, so each can be loaded directly
+    if let (Some(archive), Some(keep_builds)) = (&archive, args.keep_builds) {
+        if !output::write_manifest(
+            &modules,
+            &single_name,
+            &written,
+            &args.target,
+            false,
+            &archive.join("manifest.json"),
+            &mut staging,
+        )
+        .await
+        {
+            return None;
+        }
+        output::archive_build(archive, keep_builds, &mut staging);
+    }
+
+    if let Some(pack) = &args.pack {
+        logging::separator();
+        if !output::write_pack(&modules, &single_name, &args.target, pack, &mut staging).await {
+            return None;
+        }
+    }
+
+    if let Some(output_path) = &args.output_path {
+        let out = RustGpuBuilderOutput {
+            entry_points,
+            modules,
+        };
+
+        let out = match args.output_format {
+            OutputFormat::Json => {
+                serde_json::to_vec_pretty(&out).expect("Failed to serialize output")
+            }
+            OutputFormat::Messagepack => {
+                rmp_serde::to_vec_named(&out).expect("Failed to serialize output")
+            }
+        };
+
+        logging::separator();
+        if let Err(e) = staging.write(output_path, out).await {
+            error!("Failed to write output to {output_path:?}: {e:}");
+            return None;
+        }
+        info!("Wrote output to {output_path:?}");
+    }
+
+    if let Err(e) = staging.promote() {
+        error!("Failed to move outputs into place: {e}");
+        return None;
+    }
+
+    // Module files are listed so that deleting them invalidates the source cache and state
+    let files = match &args.output_dir {
+        Some(output_dir) => written
+            .iter()
+            .map(|module| output_dir.join(&module.file))
+            .collect(),
+        None => vec![],
+    };
+    Some(files)
+}
+
+/// Record the source hash of a successful build, so unchanged sources can skip future rebuilds.
+///
+/// The build only counts as fresh while all of its `outputs` still exist.
+fn update_source_cache(args: &ShaderBuilder, source_hash: Option<String>, outputs: &[PathBuf]) {
+    let Some(hash) = source_hash else {
+        return
+    };
+
+    if let Err(e) = args.source_cache().update(
+        &args.source_cache_key(),
+        &args.source_cache_options(),
+        hash,
+        outputs,
+    ) {
+        warn!("Failed to update source cache: {e:}");
+    }
+}
+
+/// Build each of `builders` in turn with `build`, sending a message per build on `build_tx`.
+///
+/// A panicking build is reported as [`Msg::BuildPanicked`], and the remaining builds still run.
+fn build_each(
+    builders: Vec<ShaderBuilder>,
+    build: impl Fn(&ShaderBuilder) -> Option<BuildReport>,
+    build_tx: &Sender<Msg>,
+) {
+    for builder in builders {
+        let start = Instant::now();
+        let msg = match panic::catch_unwind(AssertUnwindSafe(|| build(&builder))) {
+            Ok(Some(report)) => Msg::Build(report),
+            Ok(None) => Msg::Skipped(builder.display_name()),
+            Err(panic) => Msg::BuildPanicked(
+                builder.display_name(),
+                error::panic_message(&*panic),
+                start.elapsed(),
+            ),
+        };
+        future::block_on(build_tx.send(msg)).unwrap();
+    }
+}
+
+/// Build and write every crate and target in release mode, as per --release-on-exit.
+///
+/// Returns the exit code of the first failed build, or zero if all succeeded.
+fn build_release(args: &ShaderBuilder) -> i32 {
+    info!("Building shader in release mode...");
+    logging::separator();
+
+    let mut exit_code = 0;
+    for mut builder in args.builders() {
+        builder.release = true;
+
+        // Always build, as a source cache hit from an earlier session would otherwise
+        // leave the debug outputs of watch mode in place as the final artifacts
+        let Some(report) = builder.build_if_changed(true) else {
+            continue;
+        };
+
+        write_junit(&builder, &report);
+        write_sarif(&builder, &report);
+        match report.result {
+            Ok(result) => {
+                let written = future::block_on(handle_compile_result(
+                    result,
+                    builder,
+                    report.source_hash,
+                    report.timings,
+                    report.ephemeral_target,
+                ));
+                if !written && exit_code == 0 {
+                    exit_code = error::EXIT_FAILURE;
+                }
+            }
+            Err(e) => {
+                e.log();
+                if exit_code == 0 {
+                    exit_code = e.exit_code();
+                }
+            }
+        }
+    }
+    exit_code
+}
+
+/// Log source cache statistics as per --cache-stats, additionally printing them
+/// as a JSON event with `--message-format json`.
+fn log_cache_stats(args: &ShaderBuilder, stats: &BuildStats) {
+    let size = args.source_cache().size().unwrap_or_else(|e| {
+        warn!("Failed to measure source cache: {e:}");
+        0
+    });
+    stats.log_cache_stats(size);
+
+    if matches!(args.message_format, MessageFormat::Json) {
+        println!("{}", stats.cache_stats_json(size));
+    }
+}
+
+/// Log a summary of the session on exit, collapsed to a single line for one-shot builds.
+///
+/// With `--message-format json`, the summary is additionally printed as a JSON event on stdout.
+fn log_session_summary(args: &ShaderBuilder, stats: &BuildStats, one_shot: bool) {
+    let sizes = metrics::module_sizes();
+    if one_shot {
+        stats.log_line(&sizes);
+    } else {
+        stats.log_summary(&sizes);
+    }
+
+    if matches!(args.message_format, MessageFormat::Json) {
+        println!("{}", stats.to_json(&sizes));
+    }
+}
+
+fn main() {
+    let mut command = ShaderBuilder::command();
+    // Settings from a config file are exported as environment variables, so must be loaded before parsing
+    if let Some(path) = config::path_from_args() {
+        if let Err(e) = config::load(&command, &path) {
+            command.error(ErrorKind::Io, e).exit();
+        }
+    }
+    let matches = command.get_matches_mut();
+    if let Some(shell) = matches.get_one::<Shell>("generate_completions") {
+        completions::generate(*shell, command);
+        return;
+    }
+    if matches.get_flag("dump_config") {
+        print!("{}", config::dump(&command, &matches));
+        return;
+    }
+    // Tools are resolved without a crate path, so read their options directly
+    if matches.get_flag("list_tools") {
+        let tools = tools::ToolPaths {
+            dir: matches.get_one::<PathBuf>("external_tools_path").cloned(),
+            spirv_dis: matches.get_one::<PathBuf>("spirv_dis_path").cloned(),
+        };
+        for (name, path) in tools.list() {
+            match path {
+                Some(path) => println!("{name}: {}", path.display()),
+                None => println!("{name}: not found"),
+            }
+        }
+        return;
+    }
+    let mut args = ShaderBuilder::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Resolve colors once, and pass the choice on to every cargo invocation via its own variable
+    let color = args.color.enabled();
+    std::env::set_var("CARGO_TERM_COLOR", if color { "always" } else { "never" });
+
+    // A dry run must not touch the filesystem, so skip the log and trace files
+    logging::init(
+        args.log_time,
+        color,
+        args.log_file
+            .as_deref()
+            .filter(|_| !args.dry_run)
+            .map(|path| logging::LogFile {
+                path,
+                level: args.log_file_level,
+                append: args.log_file_append,
+            }),
+        args.trace_file.as_deref().filter(|_| !args.dry_run),
+    );
+
+    logging::separator();
+    info!("Shader Builder");
+    logging::separator();
+
+    if args.init {
+        let result = init::init(
+            &args.path_to_crate,
+            args.init_name.as_deref(),
+            args.init_example,
+            args.force,
+        );
+        if let Err(e) = &result {
+            error!("Failed to initialize shader crate: {e:}");
+        }
+        logging::shutdown();
+        std::process::exit(if result.is_ok() {
+            0
+        } else {
+            error::EXIT_FAILURE
+        });
+    }
+
+    args.resolve_watch_paths_env();
+    args.resolve_github_actions(&matches);
+    args.resolve_include_dirs();
+    args.resolve_targets(&matches);
+    args.check_capabilities();
+    args.resolve_preset();
+    args.resolve_layout();
+    args.resolve_git_source();
+
+    if args.doctor {
+        let passed = doctor::run(&args.path_to_crate, args.doctor_probe);
+        logging::shutdown();
+        std::process::exit(if passed { 0 } else { error::EXIT_FAILURE });
+    }
+
+    if args.build_all {
+        args.resolve_build_all();
+    } else if paths::is_glob(&args.path_to_crate) {
+        args.resolve_crate_glob();
+    } else {
+        args.resolve_manifest_path();
+    }
+    args.resolve_output_dir();
+    args.resolve_release_on_exit();
+    args.resolve_no_initial_build();
+
+    if args.github_actions && !args.check_first {
+        warn!("--github-actions only annotates diagnostics from the --check-first pre-pass");
+    }
+
+    if args.sarif_out.is_some() && !args.check_first {
+        warn!("--sarif-out only reports diagnostics from the --check-first pre-pass");
+    }
+
+    if args.dry_run {
+        logging::separator();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&args.config()).expect("Failed to serialize config")
+        );
+        return;
+    }
+
+    for builder in args.builders() {
+        // Lockfiles are per crate, so only check each crate once
+        if builder.target == args.targets[0] {
+            builder.check_lockfile();
+        }
+    }
+
+    if args.clear_cache {
+        for builder in args.builders() {
+            match builder.source_cache().clear(&builder.source_cache_key()) {
+                Ok(cleared) => info!(
+                    "Cleared {cleared:} source cache entries for {}",
+                    builder.display_name()
+                ),
+                Err(e) => warn!("Failed to clear source cache: {e:}"),
+            }
+        }
+        logging::separator();
+    }
+
+    if let Some(port) = args.metrics_port {
+        metrics::serve(args.metrics_bind, port);
+        logging::separator();
+    }
+
+    let initial_builders = if args.no_initial_build {
+        info!("Skipping initial build until a change is detected");
+        vec![]
+    } else {
+        info!("Building shader...");
+        args.builders()
+    };
+    logging::separator();
+    let stats = Arc::new(Mutex::new(BuildStats::default()));
+    let mut exit_code = 0;
+    let mut outcomes = vec![];
+    for builder in initial_builders {
+        let name = builder.display_name();
+        if args.multi_build() {
+            info!("Building {name}...");
+        }
+
+        if !args.warm_up {
+            if let Some(state) = builder.restore_state() {
+                state.log();
+                if state.exit_code == 0 {
+                    outcomes.push((name, TargetOutcome::Unchanged));
+                } else {
+                    if exit_code == 0 {
+                        exit_code = state.exit_code;
+                    }
+                    outcomes.push((name, TargetOutcome::Failed));
+                }
+                continue;
+            }
+        }
+
+        // Report a panicking build as a failure rather than aborting the remaining targets
+        let start = Instant::now();
+        let spinner = Spinner::start(&format!("Building {name}"), args.show_progress());
+        let report =
+            panic::catch_unwind(AssertUnwindSafe(|| builder.build_if_changed(args.warm_up)));
+        spinner.finish();
+        let report = match report {
+            Ok(Some(report)) => report,
+            Ok(None) => {
+                stats.lock().unwrap().record_cache(true);
+                outcomes.push((name, TargetOutcome::Unchanged));
+                continue;
+            }
+            Err(panic) => {
+                error!("Build panicked: {}", error::panic_message(&*panic));
+                stats.lock().unwrap().record(false, start.elapsed());
+                metrics::record_build(false, start.elapsed());
+                if exit_code == 0 {
+                    exit_code = error::EXIT_INTERNAL;
+                }
+                outcomes.push((name, TargetOutcome::Failed));
+                continue;
+            }
+        };
+
+        let mut session = stats.lock().unwrap();
+        session.record(report.result.is_ok(), report.timings.build());
+        if report.source_hash.is_some() {
+            session.record_cache(false);
+        }
+        let build = session.builds();
+        drop(session);
+        metrics::record_build(report.result.is_ok(), report.timings.build());
+        write_junit(&builder, &report);
+        write_sarif(&builder, &report);
+        match report.result {
+            Ok(result) => {
+                let written = future::block_on(handle_compile_result(
+                    result,
+                    builder.clone(),
+                    report.source_hash,
+                    report.timings,
+                    report.ephemeral_target,
+                ));
+                if written {
+                    stats.lock().unwrap().record_written(&name, build);
+                    outcomes.push((name, TargetOutcome::Succeeded));
+                } else {
+                    log_previous_outputs(&builder, &stats.lock().unwrap());
+                    if exit_code == 0 {
+                        exit_code = error::EXIT_FAILURE;
+                    }
+                    outcomes.push((name, TargetOutcome::Failed));
+                }
+            }
+            Err(e) => {
+                e.log();
+                log_previous_outputs(&builder, &stats.lock().unwrap());
+                builder.save_state(BuildState::new(report.source_hash, e.exit_code(), vec![]));
+                // Report the first failure, but carry on with the remaining builds
+                if exit_code == 0 {
+                    exit_code = e.exit_code();
+                }
+                outcomes.push((name, TargetOutcome::Failed));
+            }
+        }
+    }
+    if args.multi_build() && !outcomes.is_empty() {
+        logging::separator();
+        log_target_outcomes(&outcomes);
+    }
+    if args.cache_stats && !args.no_initial_build {
+        logging::separator();
+        log_cache_stats(&args, &stats.lock().unwrap());
+    }
+    logging::separator();
+
+    let Some(mut watch_paths) = args.watch_paths.clone() else {
+        log_session_summary(&args, &stats.lock().unwrap(), true);
+        logging::shutdown();
+        std::process::exit(exit_code)
+    };
+    watch_paths.extend(args.include_dirs.iter().cloned());
+    if args.rebuild_on_build_script_change {
+        watch_paths.extend(args.build_scripts());
+    }
+
+    if args.exit_on_error && exit_code != 0 {
+        log_session_summary(&args, &stats.lock().unwrap(), false);
+        logging::shutdown();
+        std::process::exit(exit_code)
+    }
+
+    let (change_tx, change_rx) = unbounded::<Msg>();
+    let (build_tx, build_rx) = unbounded::<Msg>();
+
+    // Builds share process-wide environment variables and output paths, so rather than exiting
+    // from the handler thread, leave it to the message bus to exit once the current build is done
+    ctrlc::set_handler({
+        let change_tx = change_tx.clone();
+        let mut interrupted = false;
+        move || {
+            if interrupted {
+                warn!("Interrupted again, exiting without waiting for the current build");
+                logging::shutdown();
+                std::process::exit(error::EXIT_FAILURE);
+            }
+            interrupted = true;
+            future::block_on(change_tx.send(Msg::Shutdown)).unwrap();
+        }
+    })
+    .expect("Failed to set Ctrl-C handler");
+
+    let restart_delay = Duration::from_millis(args.watcher_restart_delay_ms);
+    let max_restarts = args.max_watcher_restarts;
+    let mut filter = args.watch_filter();
+    let watch_paths = dedup_watch_paths(&watch_paths, &filter)
+        .into_iter()
+        .filter(|path| match check_watch_path(path) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Can't watch {path:?}: {e:}");
+                false
+            }
+        })
+        .collect::<Vec<_>>();
+    if watch_paths.is_empty() {
+        error!("No watch paths are readable, exiting");
+        logging::shutdown();
+        std::process::exit(error::EXIT_FAILURE);
+    }
+    if !args.no_gitignore {
+        filter.add_gitignores(&watch_paths);
+    }
+
+    let watcher_count = watch_paths.len();
+    let ex = Executor::new();
+
+    // Each watcher and the message bus run on a dedicated thread, while the executor
+    // runs build and output tasks on the main thread, plus any additional threads
+    // requested via --watch-threads. Builds themselves are offloaded to the `blocking`
+    // thread pool, so watchers keep delivering change events and earlier outputs
+    // keep being written for the duration of a build.
+    Parallel::new()
+        // Spawn file watchers
+        .each(watch_paths, |path| {
+            info!("Watching {path:?} for changes...");
+            let watch = panic::catch_unwind(AssertUnwindSafe(|| {
+                future::block_on(supervise_watch(
+                    &path,
+                    &filter,
+                    !args.no_follow_symlinks,
+                    Duration::from_millis(args.watch_settle_ms),
+                    restart_delay,
+                    max_restarts,
+                    change_tx.clone(),
+                ))
+            }));
+
+            // A panic is a bug rather than an environmental failure, so don't carry on without it
+            if let Err(panic) = watch {
+                error!(
+                    "Watcher for {path:?} panicked: {}",
+                    error::panic_message(&*panic)
+                );
+                logging::shutdown();
+                std::process::exit(error::EXIT_INTERNAL);
+            }
+        })
+        // Spawn message Bus
+        .add(|| {
+            // Crates rediscovered by --build-all only apply to later builds
+            let mut args = args.clone();
+            let mut building = false;
+            let mut watchers = watcher_count;
+            let mut outcomes = vec![];
+            let mut failed_exit_code = None;
+            let mut spinner = None;
+            // Outputs are written on the executor, so a build task may finish before they are
+            let mut pending_writes = 0;
+            let mut finished = false;
+            // Build scripts changed since the last build started, including during it
+            let mut changed_build_scripts = vec![];
+            let mut shutting_down = false;
+            loop {
+                match future::block_on(futures_lite::future::race(
+                    change_rx.recv(),
+                    build_rx.recv(),
+                )) {
+                    // On file change, spawn a build task
+                    Ok(Msg::Change(path)) => {
+                        if shutting_down {
+                            debug!("Ignoring change to {path:?} while shutting down");
+                            continue;
+                        }
+                        info!("Change detected: {path:?}");
+                        if args.multi_crate() && path.ends_with("Cargo.toml") {
+                            if let Err(message) = args.refresh_crates() {
+                                warn!("{message}, keeping the previous crates");
+                            }
+                        }
+                        if args.rebuild_on_build_script_change
+                            && path.file_name() == Some("build.rs".as_ref())
+                            && !changed_build_scripts.contains(&path)
+                        {
+                            changed_build_scripts.push(path.clone());
+                        }
+                        if !building {
+                            building = true;
+                            let build_scripts = std::mem::take(&mut changed_build_scripts);
+                            logging::separator();
+                            info!("Building shader...");
+                            logging::separator();
+                            spinner = Some(Spinner::start(
+                                &format!("Building {}", args.crate_name()),
+                                args.show_progress(),
+                            ));
+                            ex.spawn({
+                                let build_tx = build_tx.clone();
+                                let args = args.clone();
+                                async move {
+                                    // Builds block for their whole duration,
+                                    // so keep them off the executor thread
+                                    blocking::unblock({
+                                        let build_tx = build_tx.clone();
+                                        move || {
+                                            build_each(
+                                                args.builders(),
+                                                |builder| {
+                                                    for script in &build_scripts {
+                                                        builder.clean_if_build_script(script);
+                                                    }
+                                                    builder.build_if_changed(false)
+                                                },
+                                                &build_tx,
+                                            )
+                                        }
+                                    })
+                                    .await;
+                                    build_tx.send(Msg::Finished).await.unwrap();
+                                }
+                            })
+                            .detach();
+                        }
+                    }
+                    // On build complete, spawn a handle_compile_result task
+                    Ok(Msg::Build(report)) => {
+                        let builder = args.for_build(&report.path_to_crate, &report.target);
+                        let name = builder.display_name();
+                        let shared_stats = stats.clone();
+                        let mut stats = stats.lock().unwrap();
+                        stats.record(report.result.is_ok(), report.timings.build());
+                        let build = stats.builds();
+                        if report.source_hash.is_some() {
+                            stats.record_cache(false);
+                        }
+                        metrics::record_build(report.result.is_ok(), report.timings.build());
+                        write_junit(&builder, &report);
+                        write_sarif(&builder, &report);
+                        info!("Rebuild took {:.2?}", report.timings.build());
+
+                        match report.result {
+                            Ok(result) => {
+                                pending_writes += 1;
+                                let write = handle_compile_result(
+                                    result,
+                                    builder.clone(),
+                                    report.source_hash,
+                                    report.timings,
+                                    report.ephemeral_target,
+                                );
+                                let build_tx = build_tx.clone();
+                                ex.spawn(async move {
+                                    let written = write.await;
+                                    {
+                                        let mut stats = shared_stats.lock().unwrap();
+                                        if written {
+                                            stats.record_written(&name, build);
+                                        } else {
+                                            log_previous_outputs(&builder, &stats);
+                                        }
+                                    }
+                                    build_tx.send(Msg::Written(name, written)).await.unwrap();
+                                })
                                 .detach();
+                            }
+                            Err(e) => {
+                                e.log();
+                                log_previous_outputs(&builder, &stats);
+                                builder.save_state(BuildState::new(
+                                    report.source_hash,
+                                    e.exit_code(),
+                                    vec![],
+                                ));
+                                outcomes.push((name, TargetOutcome::Failed));
+                                // Exit once the remaining builds have been attempted
+                                if args.exit_on_error && failed_exit_code.is_none() {
+                                    failed_exit_code = Some(e.exit_code());
+                                }
+                            }
+                        }
+                        logging::separator();
+
+                        if let Some(interval) = args.stats_interval {
+                            if interval > 0 && stats.builds() % interval == 0 {
+                                stats.log_summary(&metrics::module_sizes());
+                                logging::separator();
+                            }
+                        }
+                    }
+                    // On watcher failure, carry on with any that remain
+                    Ok(Msg::WatcherFailed(path)) => {
+                        watchers -= 1;
+                        if watchers == 0 {
+                            error!("Stopped watching {path:?}, and no watchers remain, exiting");
+                            logging::shutdown();
+                            std::process::exit(error::EXIT_FAILURE);
+                        }
+                        warn!("Stopped watching {path:?}, continuing with {watchers:} watchers");
+                    }
+                    // On skipped build, carry on with the remaining builds
+                    Ok(Msg::Skipped(name)) => {
+                        stats.lock().unwrap().record_cache(true);
+                        outcomes.push((name, TargetOutcome::Unchanged));
+                        logging::separator();
+                    }
+                    // On build panic, report it as a failure and carry on with the remaining builds
+                    Ok(Msg::BuildPanicked(name, message, duration)) => {
+                        error!("Build panicked: {message}");
+                        stats.lock().unwrap().record(false, duration);
+                        metrics::record_build(false, duration);
+                        outcomes.push((name, TargetOutcome::Failed));
+                        if args.exit_on_error && failed_exit_code.is_none() {
+                            failed_exit_code = Some(error::EXIT_INTERNAL);
+                        }
+                        logging::separator();
+                    }
+                    // On outputs handled, report the build as succeeded only if they were written
+                    Ok(Msg::Written(name, written)) => {
+                        pending_writes -= 1;
+                        if written {
+                            outcomes.push((name, TargetOutcome::Succeeded));
                         } else {
-                            error!("Build failed!");
+                            outcomes.push((name, TargetOutcome::Failed));
+                            if args.exit_on_error && failed_exit_code.is_none() {
+                                failed_exit_code = Some(error::EXIT_FAILURE);
+                            }
+                        }
+                    }
+                    Ok(Msg::Finished) => {
+                        finished = true;
+                    }
+                    // On Ctrl-C, stop building on change, and exit once idle
+                    Ok(Msg::Shutdown) => {
+                        shutting_down = true;
+                        if building {
+                            info!("Exiting once the current build finishes...");
                         }
-                        println!();
-                        building = false;
                     }
                     Err(e) => {
                         panic!("{e:}")
                     }
                 }
+
+                // Once all builds have been attempted and their outputs written, wait for the next change
+                if finished && pending_writes == 0 {
+                    finished = false;
+                    if let Some(spinner) = spinner.take() {
+                        spinner.finish();
+                    }
+                    if args.multi_build() {
+                        log_target_outcomes(&outcomes);
+                        logging::separator();
+                    }
+                    outcomes.clear();
+
+                    if args.cache_stats {
+                        log_cache_stats(&args, &stats.lock().unwrap());
+                        logging::separator();
+                    }
+
+                    if let Some(exit_code) = failed_exit_code {
+                        log_session_summary(&args, &stats.lock().unwrap(), false);
+                        logging::shutdown();
+                        std::process::exit(exit_code);
+                    }
+
+                    building = false;
+                }
+
+                if shutting_down && !building {
+                    logging::separator();
+                    log_session_summary(&args, &stats.lock().unwrap(), false);
+                    let exit_code = if args.release_on_exit {
+                        logging::separator();
+                        build_release(&args)
+                    } else {
+                        0
+                    };
+                    logging::shutdown();
+                    std::process::exit(exit_code);
+                }
             }
         })
+        // Run executor on additional threads
+        .each(1..args.watch_threads, |_| loop {
+            future::block_on(ex.tick())
+        })
         // Run executor on main thread
         .finish(|| loop {
             future::block_on(ex.tick())
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settle time for test watchers, short enough to keep the tests quick.
+    const SETTLE: Duration = Duration::from_millis(100);
+
+    /// Watch `path` on a background thread as the watch loop does, returning the receiver of its changes.
+    fn watch(path: &Path, extensions: &[&str]) -> Receiver<Msg> {
+        let (change_tx, change_rx) = unbounded();
+        let path = path.to_path_buf();
+        let filter = WatchFilter::new(
+            &extensions
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect::<Vec<_>>(),
+        );
+
+        std::thread::spawn(move || {
+            let mut restarts = 0;
+            future::block_on(async_watch(
+                path,
+                &filter,
+                false,
+                SETTLE,
+                change_tx,
+                &mut restarts,
+            ))
+            .ok();
+        });
+
+        // Give the watcher time to start watching
+        std::thread::sleep(Duration::from_millis(500));
+        change_rx
+    }
+
+    /// The paths of the changes sent by a watcher, once it has had time to settle.
+    fn changes(change_rx: &Receiver<Msg>) -> Vec<PathBuf> {
+        std::thread::sleep(SETTLE * 10);
+
+        let mut changes = vec![];
+        while let Ok(msg) = change_rx.try_recv() {
+            if let Msg::Change(path) = msg {
+                changes.push(path);
+            }
+        }
+        changes
+    }
+
+    /// Parse `args` following the crate path, resolving --preset and --layout as `main` does.
+    fn resolved(args: &[&str]) -> ShaderBuilder {
+        let mut builder =
+            ShaderBuilder::try_parse_from(["rust-gpu-builder", "shader"].iter().chain(args))
+                .unwrap();
+        builder.resolve_preset();
+        builder.resolve_layout();
+        builder
+    }
+
+    #[test]
+    fn presets_combine_with_explicit_flags() {
+        // Flags in the order relax struct store, relax logical pointer, relax block layout,
+        // uniform buffer standard layout, scalar block layout and skip block layout
+        let cases: &[(&[&str], [bool; 6])] = &[
+            (&[], [false, false, false, false, false, false]),
+            (
+                &["--preset", "wgpu"],
+                [true, true, false, false, true, false],
+            ),
+            // Explicit flags are enabled on top of the preset
+            (
+                &["--preset", "wgpu", "--relax-block-layout"],
+                [true, true, true, false, true, false],
+            ),
+            (
+                &["--preset", "strict", "--relax-struct-store"],
+                [true, false, false, false, false, false],
+            ),
+            // --layout overrides the preset's layout flags, but not its relaxations
+            (
+                &["--preset", "wgpu", "--layout", "skip"],
+                [true, true, false, false, false, true],
+            ),
+            (
+                &["--preset", "vulkan-relaxed", "--layout", "strict"],
+                [true, true, false, false, false, false],
+            ),
+        ];
+
+        for (args, expected) in cases {
+            let builder = resolved(args);
+            let flags = [
+                builder.relax_struct_store,
+                builder.relax_logical_pointer,
+                builder.relax_block_layout,
+                builder.uniform_buffer_standard_layout,
+                builder.scalar_block_layout,
+                builder.skip_block_layout,
+            ];
+            assert_eq!(flags, *expected, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn save_triggers_one_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let shader = dir.path().join("lib.rs");
+        std::fs::write(&shader, "// before").unwrap();
+
+        let change_rx = watch(dir.path(), &["rs"]);
+        std::fs::write(&shader, "// after").unwrap();
+
+        let changes = changes(&change_rx);
+        assert_eq!(changes.len(), 1, "{changes:?}");
+        assert!(paths::same(
+            &changes[0],
+            &std::fs::canonicalize(&shader).unwrap()
+        ));
+    }
+
+    #[test]
+    fn save_burst_triggers_one_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let shader = dir.path().join("lib.rs");
+        let backup = dir.path().join("lib.rs~");
+        std::fs::write(&shader, "// before").unwrap();
+
+        // As Vim saves: move the file to a backup, write it anew, then delete the backup
+        let change_rx = watch(dir.path(), &["rs"]);
+        std::fs::rename(&shader, &backup).unwrap();
+        std::fs::write(&shader, "// after").unwrap();
+        std::fs::remove_file(&backup).unwrap();
+
+        let changes = changes(&change_rx);
+        assert_eq!(changes.len(), 1, "{changes:?}");
+        assert!(paths::same(
+            &changes[0],
+            &std::fs::canonicalize(&shader).unwrap()
+        ));
+    }
+
+    #[test]
+    fn filtered_change_triggers_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let change_rx = watch(dir.path(), &["rs"]);
+        std::fs::write(dir.path().join("notes.txt"), "not a shader").unwrap();
+
+        assert!(changes(&change_rx).is_empty());
+    }
+
+    #[test]
+    fn non_ascii_path_triggers_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_dir = dir.path().join("シェーダー");
+        let shader = crate_dir.join("ländlich.rs");
+        std::fs::create_dir(&crate_dir).unwrap();
+        std::fs::write(&shader, "// before").unwrap();
+
+        let change_rx = watch(&crate_dir, &["rs"]);
+        std::fs::write(&shader, "// after").unwrap();
+
+        let changes = changes(&change_rx);
+        assert_eq!(changes.len(), 1, "{changes:?}");
+        assert!(paths::same(
+            &changes[0],
+            &std::fs::canonicalize(&shader).unwrap()
+        ));
+    }
+
+    #[test]
+    fn watcher_failure_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shader");
+        std::fs::create_dir(&path).unwrap();
+
+        // Remove the path after the startup checks would have passed, but before the watcher starts
+        std::fs::remove_dir(&path).unwrap();
+        let (change_tx, change_rx) = unbounded();
+        future::block_on(supervise_watch(
+            &path,
+            &WatchFilter::new(&["rs".to_string()]),
+            false,
+            SETTLE,
+            SETTLE,
+            3,
+            change_tx,
+        ));
+
+        assert!(matches!(
+            change_rx.try_recv(),
+            Ok(Msg::WatcherFailed(failed)) if failed == path
+        ));
+    }
+
+    #[test]
+    fn changes_arrive_while_building() {
+        let dir = tempfile::tempdir().unwrap();
+        let shader = dir.path().join("lib.rs");
+        std::fs::write(&shader, "// before").unwrap();
+        let change_rx = watch(dir.path(), &["rs"]);
+
+        // Hold the build until the test is done observing changes
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let (build_tx, build_rx) = unbounded();
+        let builder = resolved(&[]);
+        let build = std::thread::spawn(move || {
+            build_each(
+                vec![builder],
+                |_| {
+                    done_rx.recv().ok();
+                    None
+                },
+                &build_tx,
+            )
+        });
+
+        std::fs::write(&shader, "// during").unwrap();
+        let changes = changes(&change_rx);
+        assert!(build_rx.is_empty(), "the build finished early");
+        assert_eq!(changes.len(), 1, "{changes:?}");
+
+        done_tx.send(()).unwrap();
+        build.join().unwrap();
+        assert!(matches!(build_rx.try_recv(), Ok(Msg::Skipped(_))));
+    }
+
+    #[test]
+    fn outputs_inside_the_crate_keep_the_source_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let shader = dir.path().join("lib.rs");
+        let output_dir = dir.path().join("out");
+        let bindings = dir.path().join("bindings.rs");
+        std::fs::write(&shader, "// before").unwrap();
+
+        let mut builder = resolved(&[
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+            "--bindings-rs-out",
+            bindings.to_str().unwrap(),
+        ]);
+        builder.path_to_crate = dir.path().to_path_buf();
+        let hash = builder.source_hash().unwrap();
+
+        std::fs::create_dir(&output_dir).unwrap();
+        std::fs::write(output_dir.join("shader.spv"), [0; 4]).unwrap();
+        std::fs::write(&bindings, "// generated").unwrap();
+        assert_eq!(builder.source_hash().unwrap(), hash);
+
+        std::fs::write(&shader, "// after").unwrap();
+        assert_ne!(builder.source_hash().unwrap(), hash);
+    }
+
+    #[test]
+    fn panicking_build_fails_and_the_rest_still_run() {
+        let builders = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                let mut builder = resolved(&[]);
+                builder.path_to_crate = name.into();
+                builder
+            })
+            .collect();
+        let (build_tx, build_rx) = unbounded();
+        build_each(
+            builders,
+            |builder| {
+                if builder.crate_name() == "b" {
+                    panic!("injected panic");
+                }
+                None
+            },
+            &build_tx,
+        );
+
+        // The bus records a BuildPanicked as a failed target
+        let mut msgs = vec![];
+        while let Ok(msg) = build_rx.try_recv() {
+            msgs.push(msg);
+        }
+        assert!(matches!(
+            msgs.as_slice(),
+            [Msg::Skipped(a), Msg::BuildPanicked(b, message, _), Msg::Skipped(c)]
+                if a == "a" && b == "b" && message == "injected panic" && c == "c"
+        ));
+    }
+}
@@ -0,0 +1,181 @@
+//! Prometheus metrics, served over HTTP as per `--metrics-port`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tiny_http::{Header, Response, Server};
+use tracing::{error, info};
+
+/// Upper bounds of the build duration histogram buckets, in seconds.
+const DURATION_BUCKETS: [f64; 9] = [0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Metrics collected over the session.
+struct Metrics {
+    successes: u64,
+    failures: u64,
+    /// Number of builds falling into each of [`DURATION_BUCKETS`], non-cumulative.
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    last_build: Option<f64>,
+    /// Size of each module in bytes, keyed by target and entry point or module name.
+    module_sizes: BTreeMap<(String, String), u64>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    successes: 0,
+    failures: 0,
+    duration_buckets: [0; DURATION_BUCKETS.len()],
+    duration_sum: 0.0,
+    last_build: None,
+    module_sizes: BTreeMap::new(),
+});
+
+/// Record the outcome and duration of a build.
+pub fn record_build(success: bool, duration: Duration) {
+    let mut metrics = METRICS.lock().unwrap();
+
+    if success {
+        metrics.successes += 1;
+    } else {
+        metrics.failures += 1;
+    }
+
+    let secs = duration.as_secs_f64();
+    if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| secs <= *bound) {
+        metrics.duration_buckets[bucket] += 1;
+    }
+    metrics.duration_sum += secs;
+
+    metrics.last_build = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|time| time.as_secs_f64());
+}
+
+/// Record the size of a compiled module.
+///
+/// `name` is the entry point of a multi-module build, or the module name of a single-module build.
+pub fn record_module_size(target: &str, name: &str, size: u64) {
+    METRICS
+        .lock()
+        .unwrap()
+        .module_sizes
+        .insert((target.to_string(), name.to_string()), size);
+}
+
+/// Size of each module recorded so far in bytes, keyed by target and entry point or module name.
+pub fn module_sizes() -> BTreeMap<(String, String), u64> {
+    METRICS.lock().unwrap().module_sizes.clone()
+}
+
+/// Escape a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Render the collected metrics in the Prometheus text exposition format.
+fn render() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut out = String::new();
+
+    out += "# HELP rust_gpu_builder_builds_total Number of shader builds, by outcome.\n";
+    out += "# TYPE rust_gpu_builder_builds_total counter\n";
+    writeln!(
+        out,
+        "rust_gpu_builder_builds_total{{status=\"success\"}} {}",
+        metrics.successes
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "rust_gpu_builder_builds_total{{status=\"failure\"}} {}",
+        metrics.failures
+    )
+    .unwrap();
+
+    out += "# HELP rust_gpu_builder_build_duration_seconds Duration of shader builds.\n";
+    out += "# TYPE rust_gpu_builder_build_duration_seconds histogram\n";
+    let mut cumulative = 0;
+    for (bound, count) in DURATION_BUCKETS.iter().zip(metrics.duration_buckets) {
+        cumulative += count;
+        writeln!(
+            out,
+            "rust_gpu_builder_build_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+        )
+        .unwrap();
+    }
+    let count = metrics.successes + metrics.failures;
+    writeln!(
+        out,
+        "rust_gpu_builder_build_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "rust_gpu_builder_build_duration_seconds_sum {}",
+        metrics.duration_sum
+    )
+    .unwrap();
+    writeln!(out, "rust_gpu_builder_build_duration_seconds_count {count}").unwrap();
+
+    if let Some(last_build) = metrics.last_build {
+        out += "# HELP rust_gpu_builder_last_build_timestamp Unix time of the last build.\n";
+        out += "# TYPE rust_gpu_builder_last_build_timestamp gauge\n";
+        writeln!(out, "rust_gpu_builder_last_build_timestamp {last_build}").unwrap();
+    }
+
+    if !metrics.module_sizes.is_empty() {
+        out += "# HELP rust_gpu_builder_spv_size_bytes Size of each compiled module.\n";
+        out += "# TYPE rust_gpu_builder_spv_size_bytes gauge\n";
+        for ((target, name), size) in &metrics.module_sizes {
+            writeln!(
+                out,
+                "rust_gpu_builder_spv_size_bytes{{target=\"{}\",entry_point=\"{}\"}} {size}",
+                escape_label(target),
+                escape_label(name)
+            )
+            .unwrap();
+        }
+    }
+
+    out
+}
+
+/// Serve metrics at `/metrics` on the given address and port from a dedicated thread.
+pub fn serve(bind: IpAddr, port: u16) {
+    let addr = SocketAddr::new(bind, port);
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Failed to start metrics server on {addr:}: {e:}");
+            return;
+        }
+    };
+    info!("Serving metrics on http://{addr:}/metrics");
+
+    std::thread::spawn(move || {
+        let content_type =
+            Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                .expect("Invalid header");
+
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                Response::from_string(render()).with_header(content_type.clone())
+            } else {
+                Response::from_string("Not found").with_status_code(404)
+            };
+
+            if let Err(e) = request.respond(response) {
+                error!("Failed to respond to metrics request: {e:}");
+            }
+        }
+    });
+}
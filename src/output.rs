@@ -0,0 +1,912 @@
+//! Writing compiled modules to disk.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rust_gpu_builder_shared::RustGpuBuilderModules;
+use sha2::{Digest, Sha256};
+
+use tracing::{error, info, warn};
+
+use crate::{call_graph, reflect, source_hash::to_hex, target, template::OutputTemplate};
+
+/// How modules written to `--output-dir` are packaged.
+#[derive(Debug, Copy, Clone)]
+pub enum ModuleFormat {
+    /// One SPIR-V binary per module.
+    Raw,
+    /// One JSON file per module, containing its entry points and base64-encoded SPIR-V.
+    Json,
+    /// One Rust source file per module, containing its SPIR-V as a `pub const` byte array.
+    Embedded,
+}
+
+impl FromStr for ModuleFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "json" => Ok(Self::Json),
+            "embedded" => Ok(Self::Embedded),
+            _ => Err("Unrecognized module format"),
+        }
+    }
+}
+
+/// Derive the name of a single-module build from the path `spirv-builder` wrote it to.
+pub fn single_module_name(path: &Path) -> String {
+    path.file_stem()
+        .expect("Module path has no file name")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The outputs of a build, staged alongside their destinations and only moved into place by
+/// [`Staging::promote`], so that a build failing partway leaves the previous outputs untouched.
+///
+/// Staged files that were never promoted are deleted on drop.
+#[derive(Default)]
+pub struct Staging {
+    /// Staged files and their destinations, in the order they were staged.
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Steps run once the staged files are in place, such as updating shared files.
+    then: Vec<Box<dyn FnOnce() -> io::Result<()> + Send>>,
+}
+
+impl Staging {
+    /// Stage `contents` to be written to `path`, creating its parent directories.
+    pub async fn write(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+
+        let staged = staged_path(path);
+        async_fs::write(&staged, contents).await?;
+        if !self.files.iter().any(|(_, dest)| dest == path) {
+            self.files.push((staged, path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    /// Run `step` once the staged files have been promoted.
+    pub fn then(&mut self, step: impl FnOnce() -> io::Result<()> + Send + 'static) {
+        self.then.push(Box::new(step));
+    }
+
+    /// Move the staged files into place, each atomically, then run the deferred steps.
+    pub fn promote(mut self) -> io::Result<()> {
+        while !self.files.is_empty() {
+            let (staged, path) = &self.files[0];
+            std::fs::rename(staged, path)?;
+            self.files.remove(0);
+        }
+
+        for step in std::mem::take(&mut self.then) {
+            step()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        for (staged, _) in &self.files {
+            std::fs::remove_file(staged).ok();
+        }
+    }
+}
+
+/// The hidden file `path` is staged to before being moved into place, ex. `.shader.spv.staged`.
+fn staged_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().expect("Output path has no file name"));
+    name.push(".staged");
+    path.with_file_name(name)
+}
+
+/// Make sure `dir` exists and is a directory before writing outputs into it, creating it if needed.
+///
+/// If it was prepared by an earlier build and has since been deleted, ex. by the user while watching,
+/// it's recreated with a warning. Returns whether it's ready, logging the error otherwise.
+pub async fn prepare_output_dir(dir: &Path) -> bool {
+    // Directories prepared by earlier builds, to tell deletion apart from the first build
+    static PREPARED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+    match async_fs::metadata(dir).await {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => {
+            error!("Output directory {dir:?} exists but isn't a directory, skipping outputs");
+            return false;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if PREPARED.lock().unwrap().contains(dir) {
+                warn!("Output directory {dir:?} was deleted, recreating it");
+            }
+
+            if let Err(e) = async_fs::create_dir_all(dir).await {
+                error!("Failed to create output directory {dir:?}: {e}");
+                return false;
+            }
+        }
+        Err(e) => {
+            error!("Failed to access output directory {dir:?}: {e}");
+            return false;
+        }
+    }
+
+    PREPARED.lock().unwrap().insert(dir.to_path_buf());
+    true
+}
+
+/// Where and how modules are written by [`write_output`].
+pub struct ModuleOutput<'a> {
+    pub dir: &'a Path,
+    pub format: ModuleFormat,
+    pub template: &'a OutputTemplate,
+    /// File extension used by the raw format.
+    pub ext: &'a str,
+    /// SPIR-V target the modules were compiled for.
+    pub target: &'a str,
+    /// If set, write content-addressed files, keeping this many per module.
+    pub content_addressed: Option<usize>,
+    /// Prepended to the file name of each module of a multi-module build.
+    pub multimodule_prefix: &'a str,
+    /// Appended to the file name of each module of a multi-module build.
+    pub multimodule_suffix: &'a str,
+    /// If set, also stage each module into this build archive, as per [`build_archive_dir`].
+    pub archive: Option<&'a Path>,
+}
+
+/// A module written by [`write_output`], as recorded in the manifest.
+pub struct WrittenModule {
+    pub name: String,
+    /// Path of the file the module was written to, relative to the output directory.
+    /// For content-addressed output, the hash-named file rather than the link to it.
+    pub file: String,
+    /// SHA-256 hash of the file's contents, as hex.
+    pub sha256: String,
+}
+
+/// Stage each compiled module for the output directory in the configured format,
+/// naming it as per the output template, and return the files staged.
+///
+/// Single-module builds are named `single_name`, multi-module builds after their entry point,
+/// wrapped in the multi-module prefix and suffix. If several modules would be written to
+/// the same path, nothing is staged, an error listing them is logged and `None` is returned.
+pub async fn write_output(
+    modules: &RustGpuBuilderModules,
+    entry_points: &[String],
+    single_name: &str,
+    output: &ModuleOutput<'_>,
+    staging: &mut Staging,
+) -> Option<Vec<WrittenModule>> {
+    let named: Vec<(&str, String, &Vec<u8>, Vec<String>)> = match modules {
+        RustGpuBuilderModules::Single(module) => {
+            vec![(
+                single_name,
+                single_name.to_string(),
+                module,
+                entry_points.to_vec(),
+            )]
+        }
+        RustGpuBuilderModules::Multi(multi) => multi
+            .iter()
+            .map(|(k, v)| {
+                let file_name = format!(
+                    "{}{k}{}",
+                    output.multimodule_prefix, output.multimodule_suffix
+                );
+                (k.as_str(), file_name, v, vec![k.clone()])
+            })
+            .collect(),
+    };
+
+    let mut files = vec![];
+    for (name, file_name, module, entry_points) in named {
+        let (ext, contents) = match output.format {
+            ModuleFormat::Raw => (output.ext, module.clone()),
+            ModuleFormat::Json => {
+                let json = serde_json::json!({
+                    "name": name,
+                    "entry_points": entry_points,
+                    "spirv": STANDARD.encode(module),
+                });
+                let json = serde_json::to_vec_pretty(&json).expect("Failed to serialize module");
+                ("json", json)
+            }
+            ModuleFormat::Embedded => (
+                "rs",
+                embedded_module(name, &entry_points, module).into_bytes(),
+            ),
+        };
+
+        let path = output.dir.join(
+            output
+                .template
+                .render(&file_name, output.target, module, ext),
+        );
+        files.push((name, path, contents));
+    }
+
+    let mut conflicts = vec![];
+    for (i, (name, path, _)) in files.iter().enumerate() {
+        let others = files[i + 1..]
+            .iter()
+            .filter(|(_, other, _)| other == path)
+            .map(|(other, _, _)| *other)
+            .collect::<Vec<_>>();
+        if !others.is_empty() && !conflicts.iter().any(|(conflict, _)| conflict == path) {
+            conflicts.push((path.clone(), [vec![*name], others].concat()));
+        }
+    }
+    if !conflicts.is_empty() {
+        for (path, names) in conflicts {
+            error!(
+                "Modules {} would all be written to {path:?}",
+                names.join(", ")
+            );
+        }
+        error!("Skipped writing modules to {:?}", output.dir);
+        return None;
+    }
+
+    let mut written = vec![];
+    for (name, path, contents) in files {
+        let sha256 = to_hex(&Sha256::digest(&contents));
+        if let Some(archive) = output.archive {
+            let relative = path.strip_prefix(output.dir).unwrap_or(&path);
+            let archived = archive.join(relative);
+            if let Err(e) = staging.write(&archived, &contents).await {
+                error!("Failed to archive module to {archived:?}: {e:}");
+                return None;
+            }
+        }
+
+        let file = match output.content_addressed {
+            Some(keep) => match write_content_addressed(&path, &contents, keep, staging).await {
+                Ok(hashed) => {
+                    info!("Wrote module to {hashed:?}, linked from {path:?}");
+                    hashed
+                }
+                Err(e) => {
+                    error!("Failed to write module to {path:?}: {e:}");
+                    return None;
+                }
+            },
+            None => match staging.write(&path, contents).await {
+                Ok(()) => {
+                    info!("Wrote module to {path:?}");
+                    path
+                }
+                Err(e) => {
+                    error!("Failed to write module to {path:?}: {e:}");
+                    return None;
+                }
+            },
+        };
+
+        written.push(WrittenModule {
+            name: name.to_string(),
+            file: file
+                .strip_prefix(output.dir)
+                .unwrap_or(&file)
+                .to_string_lossy()
+                .replace('\\', "/"),
+            sha256,
+        });
+    }
+
+    Some(written)
+}
+
+/// Stage `contents` for a file alongside `path` named after their hash, ex. `shader_1a2b3c4d.spv`,
+/// and once promoted, atomically point `path` at it.
+///
+/// Only the `keep` most recently written hash-named files for `path` are retained.
+async fn write_content_addressed(
+    path: &Path,
+    contents: &[u8],
+    keep: usize,
+    staging: &mut Staging,
+) -> io::Result<PathBuf> {
+    let stem = path
+        .file_stem()
+        .expect("Module path has no file name")
+        .to_string_lossy()
+        .into_owned();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let hash = &to_hex(&Sha256::digest(contents))[..8];
+    let hashed_name = format!("{stem}_{hash}{ext}");
+    let hashed = path.with_file_name(&hashed_name);
+
+    staging.write(&hashed, contents).await?;
+    staging.then({
+        let path = path.to_path_buf();
+        let hashed = hashed.clone();
+        move || {
+            link_latest(&path, &hashed_name)?;
+            prune_versions(&path, &hashed, &stem, &ext, keep)
+        }
+    });
+
+    Ok(hashed)
+}
+
+/// Delete all but the `keep` most recently written hash-named files for `path`, never deleting `latest`.
+fn prune_versions(
+    path: &Path,
+    latest: &Path,
+    stem: &str,
+    ext: &str,
+    keep: usize,
+) -> io::Result<()> {
+    // Collect hash-named files for this module, most recent first
+    let dir = path.parent().expect("Module path has no parent");
+    let mut versions = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_version = name
+            .strip_prefix(&format!("{stem}_"))
+            .and_then(|rest| rest.strip_suffix(ext))
+            .map(|hash| hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+            .unwrap_or_default();
+
+        if is_version {
+            versions.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+    versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    for (_, version) in versions.into_iter().skip(keep.max(1)) {
+        if version != latest {
+            std::fs::remove_file(&version)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Atomically replace `path` with a symlink to `target`, a file name in the same directory.
+#[cfg(unix)]
+fn link_latest(path: &Path, target: &str) -> io::Result<()> {
+    let tmp = path.with_extension("latest.tmp");
+    std::fs::remove_file(&tmp).ok();
+    std::os::unix::fs::symlink(target, &tmp)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Atomically write the name of `target` to a `.latest` file alongside `path`,
+/// as symlinks require elevated privileges on Windows.
+#[cfg(not(unix))]
+fn link_latest(path: &Path, target: &str) -> io::Result<()> {
+    let mut latest = path.as_os_str().to_owned();
+    latest.push(".latest");
+    let latest = PathBuf::from(latest);
+
+    let tmp = latest.with_extension("latest.tmp");
+    std::fs::write(&tmp, target)?;
+    std::fs::rename(&tmp, latest)
+}
+
+/// Name of the link to the newest archived build, as per [`archive_build`].
+const LATEST_BUILD: &str = "latest";
+
+/// A new directory under `output_dir` to archive a build's modules and manifest into,
+/// ex. `builds/1697371234567`, named after the current time in milliseconds so builds sort by age.
+pub fn build_archive_dir(output_dir: &Path) -> PathBuf {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_millis();
+    output_dir.join("builds").join(format!("{millis:013}"))
+}
+
+/// Once promoted, point `builds/latest` at `archive` and delete all but the `keep` newest builds.
+///
+/// Builds without a manifest, ex. left behind by a build that failed partway, are deleted too.
+pub fn archive_build(archive: &Path, keep: usize, staging: &mut Staging) {
+    let archive = archive.to_path_buf();
+    staging.then(move || {
+        let builds = archive.parent().expect("Build archive has no parent");
+        let id = archive
+            .file_name()
+            .expect("Build archive has no name")
+            .to_string_lossy()
+            .into_owned();
+
+        link_latest_build(builds, &id)?;
+        info!("Archived build to {archive:?}");
+        prune_builds(builds, &id, keep)
+    });
+}
+
+/// Delete all but the `keep` newest archived builds in `builds`, never deleting `current`.
+fn prune_builds(builds: &Path, current: &str, keep: usize) -> io::Result<()> {
+    let mut archived = vec![];
+    for entry in std::fs::read_dir(builds)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name != current
+            && name.chars().all(|c| c.is_ascii_digit())
+            && entry.file_type()?.is_dir()
+        {
+            archived.push((name, entry.path()));
+        }
+    }
+    // Names are zero-padded timestamps, so sort newest first
+    archived.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let (complete, incomplete): (Vec<_>, Vec<_>) = archived
+        .into_iter()
+        .partition(|(_, path)| path.join("manifest.json").is_file());
+
+    for (_, path) in complete.into_iter().skip(keep.max(1) - 1).chain(incomplete) {
+        std::fs::remove_dir_all(path)?;
+    }
+
+    Ok(())
+}
+
+/// Atomically replace `builds/latest` with a symlink to the archived build `id`.
+#[cfg(unix)]
+fn link_latest_build(builds: &Path, id: &str) -> io::Result<()> {
+    let tmp = builds.join(format!(".{LATEST_BUILD}.tmp"));
+    std::fs::remove_file(&tmp).ok();
+    std::os::unix::fs::symlink(id, &tmp)?;
+    std::fs::rename(&tmp, builds.join(LATEST_BUILD))
+}
+
+/// Replace `builds/latest` with a copy of the archived build `id`,
+/// as symlinks require elevated privileges on Windows.
+#[cfg(not(unix))]
+fn link_latest_build(builds: &Path, id: &str) -> io::Result<()> {
+    fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+            } else {
+                std::fs::copy(entry.path(), to.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Copy alongside, then swap, so `latest` is only briefly missing
+    let tmp = builds.join(format!(".{LATEST_BUILD}.tmp"));
+    let old = builds.join(format!(".{LATEST_BUILD}.old"));
+    std::fs::remove_dir_all(&tmp).ok();
+    std::fs::remove_dir_all(&old).ok();
+    copy_dir(&builds.join(id), &tmp)?;
+
+    let latest = builds.join(LATEST_BUILD);
+    if latest.exists() {
+        std::fs::rename(&latest, &old)?;
+    }
+    std::fs::rename(&tmp, &latest)?;
+    std::fs::remove_dir_all(&old).ok();
+    Ok(())
+}
+
+/// Stage `#[repr(C)]` mirrors of the uniform and push constant structs in `modules` for `path`.
+///
+/// Returns whether they were staged, logging the error otherwise.
+pub async fn write_bindings(
+    modules: &RustGpuBuilderModules,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let bindings = match modules {
+        RustGpuBuilderModules::Single(module) => reflect::bindings([module.as_slice()]),
+        RustGpuBuilderModules::Multi(multi) => reflect::bindings(multi.values().map(Vec::as_slice)),
+    };
+
+    let bindings = match bindings {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            error!("Failed to parse module for bindings: {e}");
+            return false;
+        }
+    };
+
+    match staging.write(path, bindings).await {
+        Ok(()) => {
+            info!("Wrote bindings to {path:?}");
+            true
+        }
+        Err(e) => {
+            error!("Failed to write bindings to {path:?}: {e:}");
+            false
+        }
+    }
+}
+
+/// Stage the descriptor sets of `modules`, with per-binding stage flag recommendations, for `path` as JSON.
+///
+/// Returns whether they were staged, logging the error otherwise.
+pub async fn write_descriptor_sets(
+    modules: &RustGpuBuilderModules,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let descriptor_sets = match modules {
+        RustGpuBuilderModules::Single(module) => reflect::descriptor_sets([module.as_slice()]),
+        RustGpuBuilderModules::Multi(multi) => {
+            reflect::descriptor_sets(multi.values().map(Vec::as_slice))
+        }
+    };
+
+    let descriptor_sets = match descriptor_sets {
+        Ok(descriptor_sets) => descriptor_sets,
+        Err(e) => {
+            error!("Failed to parse module for descriptor sets: {e}");
+            return false;
+        }
+    };
+    let descriptor_sets =
+        serde_json::to_vec_pretty(&descriptor_sets).expect("Failed to serialize descriptor sets");
+
+    match staging.write(path, descriptor_sets).await {
+        Ok(()) => {
+            info!("Wrote descriptor sets to {path:?}");
+            true
+        }
+        Err(e) => {
+            error!("Failed to write descriptor sets to {path:?}: {e:}");
+            false
+        }
+    }
+}
+
+/// Stage the minimum Vulkan version required by the capabilities of `modules` for `path` as JSON,
+/// along with the extensions needed on that version and the requirements of each capability.
+///
+/// Warns if `target` is for an earlier Vulkan version.
+/// Returns whether the report was staged, logging the error otherwise.
+pub async fn write_min_vulkan_version(
+    modules: &RustGpuBuilderModules,
+    target: &str,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let capabilities = match modules {
+        RustGpuBuilderModules::Single(module) => reflect::capabilities([module.as_slice()]),
+        RustGpuBuilderModules::Multi(multi) => {
+            reflect::capabilities(multi.values().map(Vec::as_slice))
+        }
+    };
+
+    let capabilities = match capabilities {
+        Ok(capabilities) => capabilities,
+        Err(e) => {
+            error!("Failed to parse module for capabilities: {e}");
+            return false;
+        }
+    };
+
+    let requirements = capabilities
+        .iter()
+        .map(|capability| (capability, target::vulkan_requirement(capability)))
+        .collect::<Vec<_>>();
+    let min_version = requirements
+        .iter()
+        .map(|(_, requirement)| requirement.version)
+        .max()
+        .unwrap_or((1, 0));
+    let version = |(major, minor): (u8, u8)| format!("{major}.{minor}");
+
+    // Extensions are only needed for capabilities that aren't core in the minimum version
+    let extensions = requirements
+        .iter()
+        .filter(|(_, requirement)| requirement.core.map_or(true, |core| core > min_version))
+        .filter_map(|(_, requirement)| requirement.extension)
+        .collect::<BTreeSet<_>>();
+
+    if let Some(target_version) = target::vulkan_version(target) {
+        if min_version > target_version {
+            warn!(
+                "Shader requires Vulkan {}, but {target} targets Vulkan {}",
+                version(min_version),
+                version(target_version)
+            );
+        }
+    }
+
+    let json = serde_json::json!({
+        "min_vulkan_version": version(min_version),
+        "extensions": extensions,
+        "capabilities": requirements
+            .iter()
+            .map(|(capability, requirement)| {
+                serde_json::json!({
+                    "capability": capability,
+                    "vulkan_version": version(requirement.version),
+                    "extension": requirement.extension,
+                    "core_version": requirement.core.map(version),
+                })
+            })
+            .collect::<Vec<_>>(),
+    });
+    let json = serde_json::to_vec_pretty(&json).expect("Failed to serialize Vulkan requirements");
+
+    match staging.write(path, json).await {
+        Ok(()) => {
+            info!(
+                "Wrote minimum Vulkan version {} to {path:?}",
+                version(min_version)
+            );
+            true
+        }
+        Err(e) => {
+            error!("Failed to write Vulkan requirements to {path:?}: {e:}");
+            false
+        }
+    }
+}
+
+/// Stage a DOT call graph of the functions in `modules` for `path`.
+///
+/// Single-module builds are named `single_name`, multi-module builds after their entry point.
+/// Returns whether the graph was staged, logging the error otherwise.
+pub async fn write_call_graph(
+    modules: &RustGpuBuilderModules,
+    single_name: &str,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let graph = call_graph::dot(
+        named_modules(modules, single_name)
+            .into_iter()
+            .map(|(name, module)| (name, module.as_slice())),
+    );
+
+    let graph = match graph {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("Failed to parse module for call graph: {e}");
+            return false;
+        }
+    };
+
+    match staging.write(path, graph).await {
+        Ok(()) => {
+            info!("Wrote call graph to {path:?}");
+            true
+        }
+        Err(e) => {
+            error!("Failed to write call graph to {path:?}: {e:}");
+            false
+        }
+    }
+}
+
+/// Pair each module with its name: `single_name` for single-module builds,
+/// or its entry point for multi-module builds.
+fn named_modules<'a>(
+    modules: &'a RustGpuBuilderModules,
+    single_name: &'a str,
+) -> Vec<(&'a str, &'a Vec<u8>)> {
+    match modules {
+        RustGpuBuilderModules::Single(module) => vec![(single_name, module)],
+        RustGpuBuilderModules::Multi(multi) => multi.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+    }
+}
+
+/// List each entry point's name, execution model and module, as recorded in manifests.
+fn manifest_entry_points(
+    modules: &RustGpuBuilderModules,
+    single_name: &str,
+) -> Result<Vec<serde_json::Value>, String> {
+    let mut entry_points = vec![];
+    for (module_name, module) in named_modules(modules, single_name) {
+        let module_entry_points = reflect::entry_points(module)
+            .map_err(|e| format!("Failed to parse module {module_name}: {e}"))?;
+
+        for (name, execution_model) in module_entry_points {
+            entry_points.push(serde_json::json!({
+                "name": name,
+                "execution_model": format!("{execution_model:?}"),
+                "module": module_name,
+            }));
+        }
+    }
+
+    Ok(entry_points)
+}
+
+/// Write a JSON manifest listing each entry point's execution model and module to `path`,
+/// along with the file and hash of each module in `written`, if any.
+///
+/// Single-module builds are named `single_name`, multi-module builds after their entry point.
+///
+/// If `multi_target` is set, the entry points and files are recorded under `target`, alongside those
+/// of other targets already in the manifest, once the staged outputs are promoted.
+/// Returns whether the manifest was staged, logging the error otherwise.
+pub async fn write_manifest(
+    modules: &RustGpuBuilderModules,
+    single_name: &str,
+    written: &[WrittenModule],
+    target: &str,
+    multi_target: bool,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let entry_points = match manifest_entry_points(modules, single_name) {
+        Ok(entry_points) => entry_points,
+        Err(e) => {
+            error!("{e}, skipping manifest");
+            return false;
+        }
+    };
+
+    let mut entry = serde_json::json!({ "entry_points": entry_points });
+    if !written.is_empty() {
+        entry["modules"] = written
+            .iter()
+            .map(|module| {
+                (
+                    module.name.clone(),
+                    serde_json::json!({ "file": module.file, "sha256": module.sha256 }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+
+    if !multi_target {
+        entry["target"] = target.into();
+        let manifest = entry;
+        let manifest = serde_json::to_vec_pretty(&manifest).expect("Failed to serialize manifest");
+
+        return match staging.write(path, manifest).await {
+            Ok(()) => {
+                info!("Wrote manifest to {path:?}");
+                true
+            }
+            Err(e) => {
+                error!("Failed to write manifest to {path:?}: {e:}");
+                false
+            }
+        };
+    }
+
+    // The manifest is shared with other targets, so it's updated in place once this
+    // target's outputs are promoted, rather than staged from its current contents
+    let path = path.to_path_buf();
+    let target = target.to_string();
+    staging.then(move || {
+        // Targets' outputs are written concurrently, so serialize updates to the shared manifest
+        static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+        let _lock = MANIFEST_LOCK.lock().unwrap();
+
+        let mut manifest = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+            .filter(|manifest| manifest["targets"].is_object())
+            .unwrap_or_else(|| serde_json::json!({ "targets": {} }));
+        manifest["targets"][&target] = entry;
+        let manifest = serde_json::to_vec_pretty(&manifest).expect("Failed to serialize manifest");
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let staged = staged_path(&path);
+        std::fs::write(&staged, manifest)?;
+        std::fs::rename(&staged, &path)?;
+        info!("Wrote {target} manifest to {path:?}");
+        Ok(())
+    });
+
+    true
+}
+
+/// Magic bytes identifying a `--pack` container.
+const PACK_MAGIC: &[u8; 4] = b"RGPK";
+
+/// Version of the `--pack` container format.
+const PACK_VERSION: u32 = 1;
+
+/// Write all modules, along with their manifest, into a single container at `path`.
+///
+/// The container is little-endian: the magic `RGPK`, the format version and the entry count
+/// as `u32`s, then for each entry its name length as a `u32`, its UTF-8 name, its data length
+/// as a `u64` and its data. The first entry is `manifest.json`, as written by `--manifest`,
+/// followed by each module as `<name>.spv`.
+///
+/// Returns whether the pack was staged, logging the error otherwise.
+pub async fn write_pack(
+    modules: &RustGpuBuilderModules,
+    single_name: &str,
+    target: &str,
+    path: &Path,
+    staging: &mut Staging,
+) -> bool {
+    let entry_points = match manifest_entry_points(modules, single_name) {
+        Ok(entry_points) => entry_points,
+        Err(e) => {
+            error!("{e}, skipping pack");
+            return false;
+        }
+    };
+    let manifest = serde_json::json!({ "target": target, "entry_points": entry_points });
+    let manifest = serde_json::to_vec_pretty(&manifest).expect("Failed to serialize manifest");
+
+    let named = named_modules(modules, single_name);
+    let mut entries = vec![("manifest.json".to_string(), manifest.as_slice())];
+    entries.extend(
+        named
+            .iter()
+            .map(|(name, module)| (format!("{name}.spv"), module.as_slice())),
+    );
+
+    let mut pack = PACK_MAGIC.to_vec();
+    pack.extend(PACK_VERSION.to_le_bytes());
+    pack.extend((entries.len() as u32).to_le_bytes());
+    for (name, data) in &entries {
+        pack.extend((name.len() as u32).to_le_bytes());
+        pack.extend(name.as_bytes());
+        pack.extend((data.len() as u64).to_le_bytes());
+        pack.extend(*data);
+    }
+
+    match staging.write(path, pack).await {
+        Ok(()) => {
+            info!("Wrote {} modules to pack {path:?}", named.len());
+            true
+        }
+        Err(e) => {
+            error!("Failed to write pack to {path:?}: {e:}");
+            false
+        }
+    }
+}
+
+/// Generate Rust source exposing `module` as a `pub const` byte array.
+fn embedded_module(name: &str, entry_points: &[String], module: &[u8]) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        ident.insert(0, '_');
+    }
+
+    let mut out = String::from("// Generated by rust-gpu-builder. Do not edit.\n\n");
+    out += &format!("/// SPIR-V module `{name}`.\n///\n/// Entry points:\n");
+    for entry_point in entry_points {
+        out += &format!("/// - `{entry_point}`\n");
+    }
+    out += &format!("pub const {ident}: &[u8] = &[\n");
+    for chunk in module.chunks(16) {
+        let line = chunk
+            .iter()
+            .map(|byte| format!("0x{byte:02x},"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        out += &format!("    {line}\n");
+    }
+    out += "];\n";
+
+    out
+}
@@ -0,0 +1,174 @@
+//! Path normalization for comparing watched paths against those reported by the watcher,
+//! and glob expansion of crate paths.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// Strip the verbatim prefix from a Windows path, ex. `\\?\C:\shaders` to `C:\shaders`
+/// and `\\?\UNC\server\share` to `\\server\share`.
+///
+/// `std::fs::canonicalize` returns verbatim paths on Windows, while file watchers report plain ones.
+/// Other paths, including drive-relative ones like `C:shaders`, are returned unchanged.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return path.to_path_buf()
+    };
+
+    let plain = match prefix.kind() {
+        Prefix::VerbatimDisk(disk) => format!("{}:", disk as char),
+        Prefix::VerbatimUNC(server, share) => format!(
+            r"\\{}\{}",
+            server.to_string_lossy(),
+            share.to_string_lossy()
+        ),
+        _ => return path.to_path_buf(),
+    };
+
+    let mut normalized = PathBuf::from(plain);
+    normalized.extend(components);
+    normalized
+}
+
+/// Whether `path` contains glob metacharacters: `*`, `?` or `[`.
+pub fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// The leading components of a glob pattern without metacharacters, ex. `shaders` for `shaders/*/`.
+pub fn glob_base(pattern: &Path) -> PathBuf {
+    let base = pattern
+        .components()
+        .take_while(|component| !is_glob(Path::new(component.as_os_str())))
+        .collect::<PathBuf>();
+
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    }
+}
+
+/// Expand a glob pattern into the paths it matches, in order.
+///
+/// Patterns are expanded here rather than by the shell, so that they also work in Windows `cmd`
+/// and in config files. A pattern matching nothing is an error.
+pub fn expand_glob(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    let paths = glob::glob(&pattern.to_string_lossy())
+        .map_err(|e| format!("Invalid glob pattern {pattern:?}: {e}"))?;
+
+    let mut matches = paths
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to expand glob pattern {pattern:?}: {e}"))?;
+    if matches.is_empty() {
+        return Err(format!("Glob pattern {pattern:?} matches nothing"));
+    }
+
+    matches.sort();
+    Ok(matches)
+}
+
+/// Whether `a` and `b` refer to the same location, ignoring verbatim prefixes and trailing separators,
+/// and case on Windows.
+pub fn same(a: &Path, b: &Path) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    if cfg!(windows) {
+        // Compare as paths rather than strings, so that trailing separators are ignored
+        let lowercase = |path: &Path| PathBuf::from(path.to_string_lossy().to_lowercase());
+        lowercase(&a) == lowercase(&b)
+    } else {
+        a == b
+    }
+}
+
+/// Whether `path` is `dir` or lies inside it, ignoring verbatim prefixes, and case on Windows.
+pub fn inside(path: &Path, dir: &Path) -> bool {
+    let (path, dir) = (normalize(path), normalize(dir));
+    if cfg!(windows) {
+        let lowercase = |path: &Path| PathBuf::from(path.to_string_lossy().to_lowercase());
+        lowercase(&path).starts_with(lowercase(&dir))
+    } else {
+        path.starts_with(dir)
+    }
+}
+
+/// `path` made absolute and canonical, without a verbatim prefix.
+///
+/// Outputs may not have been written yet, so a missing path is resolved against its nearest existing ancestor.
+pub fn absolute(path: &Path) -> PathBuf {
+    if let Ok(canonical) = std::fs::canonicalize(path) {
+        return normalize(&canonical);
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => absolute(parent).join(name),
+        _ => std::fs::canonicalize(".")
+            .map(|cwd| normalize(&cwd).join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_paths_are_unchanged() {
+        for path in ["shaders/lib.rs", "/shaders/lib.rs", "lib.rs", ""] {
+            assert_eq!(normalize(Path::new(path)), Path::new(path));
+        }
+    }
+
+    #[test]
+    fn trailing_separators_are_ignored() {
+        assert!(same(Path::new("shaders/"), Path::new("shaders")));
+        assert!(same(Path::new("/shaders/src/"), Path::new("/shaders/src")));
+        assert!(!same(Path::new("/shaders/src"), Path::new("/shaders")));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn case_matters_outside_windows() {
+        assert!(!same(
+            Path::new("/Shaders/lib.rs"),
+            Path::new("/shaders/lib.rs")
+        ));
+        // Backslashes are ordinary characters, so verbatim-looking paths are left alone
+        let verbatim = Path::new(r"\\?\C:\shaders");
+        assert_eq!(normalize(verbatim), verbatim);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn verbatim_prefixes_are_stripped() {
+        let cases = [
+            (r"\\?\C:\shaders\lib.rs", r"C:\shaders\lib.rs"),
+            (r"\\?\C:\", r"C:\"),
+            (r"\\?\UNC\server\share\lib.rs", r"\\server\share\lib.rs"),
+            // Other prefixes, and drive-relative paths, are left alone
+            (r"C:\shaders\lib.rs", r"C:\shaders\lib.rs"),
+            (r"C:shaders", r"C:shaders"),
+            (r"\\server\share\lib.rs", r"\\server\share\lib.rs"),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(normalize(Path::new(path)), Path::new(expected), "{path}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn same_ignores_case_and_verbatim_prefixes() {
+        let cases = [
+            (r"C:\Shaders\lib.rs", r"c:\shaders\LIB.rs"),
+            (r"\\?\C:\shaders\lib.rs", r"C:\shaders\lib.rs"),
+            (r"\\?\c:\Shaders\", r"C:\shaders"),
+            (r"\\?\UNC\Server\share\lib.rs", r"\\server\share\lib.rs"),
+        ];
+
+        for (a, b) in cases {
+            assert!(same(Path::new(a), Path::new(b)), "{a} and {b}");
+            assert!(same(Path::new(b), Path::new(a)), "{b} and {a}");
+        }
+        assert!(!same(Path::new(r"C:\shaders"), Path::new(r"D:\shaders")));
+    }
+}
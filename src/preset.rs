@@ -0,0 +1,109 @@
+//! Curated combinations of relaxation and layout flags, as per `--preset`.
+
+use std::str::FromStr;
+
+/// A named combination of relaxation and layout flags.
+///
+/// A preset only enables flags, so flags passed alongside it are enabled as well.
+#[derive(Debug, Copy, Clone)]
+pub struct Preset {
+    pub name: &'static str,
+    pub relax_struct_store: bool,
+    pub relax_logical_pointer: bool,
+    pub relax_block_layout: bool,
+    pub uniform_buffer_standard_layout: bool,
+    pub scalar_block_layout: bool,
+    pub skip_block_layout: bool,
+}
+
+/// All presets accepted by `--preset`.
+pub const PRESETS: &[Preset] = &[
+    // Standard validation rules, with no relaxations
+    Preset {
+        name: "strict",
+        relax_struct_store: false,
+        relax_logical_pointer: false,
+        relax_block_layout: false,
+        uniform_buffer_standard_layout: false,
+        scalar_block_layout: false,
+        skip_block_layout: false,
+    },
+    // The relaxations commonly needed by shaders loaded through wgpu
+    Preset {
+        name: "wgpu",
+        relax_struct_store: true,
+        relax_logical_pointer: true,
+        relax_block_layout: false,
+        uniform_buffer_standard_layout: false,
+        scalar_block_layout: true,
+        skip_block_layout: false,
+    },
+    // Vulkan 1.1+ with VK_KHR_relaxed_block_layout, which is core from 1.1 onwards
+    Preset {
+        name: "vulkan-relaxed",
+        relax_struct_store: true,
+        relax_logical_pointer: true,
+        relax_block_layout: true,
+        uniform_buffer_standard_layout: false,
+        scalar_block_layout: false,
+        skip_block_layout: false,
+    },
+];
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PRESETS
+            .iter()
+            .find(|preset| preset.name == s)
+            .copied()
+            .ok_or_else(|| {
+                let names = PRESETS
+                    .iter()
+                    .map(|preset| preset.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Unrecognized preset {s:?}, expected one of {names}")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The flags each preset expands to, in field order: relax struct store, relax logical pointer,
+    /// relax block layout, uniform buffer standard layout, scalar block layout and skip block layout.
+    const EXPANSIONS: &[(&str, [bool; 6])] = &[
+        ("strict", [false, false, false, false, false, false]),
+        ("wgpu", [true, true, false, false, true, false]),
+        ("vulkan-relaxed", [true, true, true, false, false, false]),
+    ];
+
+    fn flags(preset: &Preset) -> [bool; 6] {
+        [
+            preset.relax_struct_store,
+            preset.relax_logical_pointer,
+            preset.relax_block_layout,
+            preset.uniform_buffer_standard_layout,
+            preset.scalar_block_layout,
+            preset.skip_block_layout,
+        ]
+    }
+
+    #[test]
+    fn presets_expand_to_their_flags() {
+        assert_eq!(PRESETS.len(), EXPANSIONS.len(), "Untested preset");
+        for (name, expected) in EXPANSIONS {
+            let preset = name.parse::<Preset>().unwrap();
+            assert_eq!(flags(&preset), *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn unknown_presets_are_rejected() {
+        let e = "vulkan".parse::<Preset>().unwrap_err();
+        assert!(e.contains("strict, wgpu, vulkan-relaxed"), "{e}");
+    }
+}
@@ -0,0 +1,51 @@
+//! Spinner shown while building, if the `progress` feature is enabled.
+
+#[cfg(feature = "progress")]
+use std::{io, time::Duration};
+
+#[cfg(feature = "progress")]
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "progress")]
+use is_terminal::IsTerminal;
+
+/// An elapsed-time spinner, drawn on stderr until finished.
+///
+/// Does nothing if the `progress` feature is disabled.
+pub struct Spinner {
+    #[cfg(feature = "progress")]
+    bar: Option<ProgressBar>,
+}
+
+#[cfg(feature = "progress")]
+impl Spinner {
+    /// Start spinning with the given message, if `enabled` and stderr is a terminal.
+    pub fn start(message: &str, enabled: bool) -> Self {
+        let bar = (enabled && io::stderr().is_terminal()).then(|| {
+            let bar = ProgressBar::new_spinner().with_message(message.to_string());
+            bar.set_style(
+                ProgressStyle::with_template("{spinner} {msg} [{elapsed}]")
+                    .expect("Invalid spinner template"),
+            );
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        });
+
+        Spinner { bar }
+    }
+
+    /// Stop spinning and clear the spinner.
+    pub fn finish(self) {
+        if let Some(bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(not(feature = "progress"))]
+impl Spinner {
+    pub fn start(_message: &str, _enabled: bool) -> Self {
+        Spinner {}
+    }
+
+    pub fn finish(self) {}
+}
@@ -0,0 +1,1153 @@
+//! Reflection over compiled SPIR-V modules.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use rspirv::{
+    binary::ParseState,
+    dr::{Instruction, Module, Operand},
+    spirv::{BuiltIn, Decoration, Dim, ExecutionModel, Op, StorageClass, Word},
+};
+
+use crate::transform;
+
+/// Types, names and layout decorations gathered from a module.
+struct Reflection {
+    types: BTreeMap<Word, Instruction>,
+    constants: BTreeMap<Word, u32>,
+    names: BTreeMap<Word, String>,
+    member_names: BTreeMap<(Word, u32), String>,
+    member_offsets: BTreeMap<(Word, u32), u32>,
+    member_matrix_strides: BTreeMap<(Word, u32), u32>,
+    row_major_members: BTreeSet<(Word, u32)>,
+    array_strides: BTreeMap<Word, u32>,
+    descriptor_sets: BTreeMap<Word, u32>,
+    bindings: BTreeMap<Word, u32>,
+    locations: BTreeMap<Word, u32>,
+    built_ins: BTreeMap<Word, BuiltIn>,
+    buffer_blocks: BTreeSet<Word>,
+}
+
+impl Reflection {
+    fn new(module: &Module) -> Self {
+        let mut reflection = Reflection {
+            types: Default::default(),
+            constants: Default::default(),
+            names: Default::default(),
+            member_names: Default::default(),
+            member_offsets: Default::default(),
+            member_matrix_strides: Default::default(),
+            row_major_members: Default::default(),
+            array_strides: Default::default(),
+            descriptor_sets: Default::default(),
+            bindings: Default::default(),
+            locations: Default::default(),
+            built_ins: Default::default(),
+            buffer_blocks: Default::default(),
+        };
+
+        for inst in &module.debug_names {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (Op::Name, [Operand::IdRef(id), Operand::LiteralString(name)]) => {
+                    reflection.names.insert(*id, name.clone());
+                }
+                (
+                    Op::MemberName,
+                    [Operand::IdRef(id), Operand::LiteralInt32(member), Operand::LiteralString(name)],
+                ) => {
+                    reflection.member_names.insert((*id, *member), name.clone());
+                }
+                _ => (),
+            }
+        }
+
+        for inst in &module.annotations {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (
+                    Op::MemberDecorate,
+                    [Operand::IdRef(id), Operand::LiteralInt32(member), Operand::Decoration(Decoration::Offset), Operand::LiteralInt32(offset)],
+                ) => {
+                    reflection.member_offsets.insert((*id, *member), *offset);
+                }
+                (
+                    Op::MemberDecorate,
+                    [Operand::IdRef(id), Operand::LiteralInt32(member), Operand::Decoration(Decoration::MatrixStride), Operand::LiteralInt32(stride)],
+                ) => {
+                    reflection
+                        .member_matrix_strides
+                        .insert((*id, *member), *stride);
+                }
+                (
+                    Op::MemberDecorate,
+                    [Operand::IdRef(id), Operand::LiteralInt32(member), Operand::Decoration(Decoration::RowMajor)],
+                ) => {
+                    reflection.row_major_members.insert((*id, *member));
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::ArrayStride), Operand::LiteralInt32(stride)],
+                ) => {
+                    reflection.array_strides.insert(*id, *stride);
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::DescriptorSet), Operand::LiteralInt32(set)],
+                ) => {
+                    reflection.descriptor_sets.insert(*id, *set);
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::Binding), Operand::LiteralInt32(binding)],
+                ) => {
+                    reflection.bindings.insert(*id, *binding);
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::Location), Operand::LiteralInt32(location)],
+                ) => {
+                    reflection.locations.insert(*id, *location);
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::BuiltIn), Operand::BuiltIn(built_in)],
+                ) => {
+                    reflection.built_ins.insert(*id, *built_in);
+                }
+                (
+                    Op::Decorate,
+                    [Operand::IdRef(id), Operand::Decoration(Decoration::BufferBlock)],
+                ) => {
+                    reflection.buffer_blocks.insert(*id);
+                }
+                _ => (),
+            }
+        }
+
+        for inst in &module.types_global_values {
+            let Some(id) = inst.result_id else {
+                continue;
+            };
+
+            match inst.class.opcode {
+                Op::Constant => {
+                    if let [Operand::LiteralInt32(value)] = inst.operands.as_slice() {
+                        reflection.constants.insert(id, *value);
+                    }
+                }
+                Op::Variable => (),
+                _ => {
+                    reflection.types.insert(id, inst.clone());
+                }
+            }
+        }
+
+        reflection
+    }
+
+    /// Return the ids of the struct types backing `Uniform` and `PushConstant` variables.
+    fn block_structs(&self, module: &Module) -> Vec<Word> {
+        module
+            .types_global_values
+            .iter()
+            .filter(|inst| inst.class.opcode == Op::Variable)
+            .filter(|inst| {
+                matches!(
+                    inst.operands.first(),
+                    Some(Operand::StorageClass(
+                        StorageClass::Uniform | StorageClass::PushConstant
+                    ))
+                )
+            })
+            .filter_map(|inst| {
+                let pointer = self.types.get(&inst.result_type?)?;
+                match pointer.operands.as_slice() {
+                    [Operand::StorageClass(_), Operand::IdRef(pointee)] => Some(*pointee),
+                    _ => None,
+                }
+            })
+            .filter(|id| self.opcode(*id) == Some(Op::TypeStruct))
+            .collect()
+    }
+
+    fn opcode(&self, id: Word) -> Option<Op> {
+        self.types.get(&id).map(|inst| inst.class.opcode)
+    }
+
+    fn id_operands(&self, id: Word) -> Vec<Word> {
+        self.types[&id]
+            .operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::IdRef(id) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn literal(&self, id: Word) -> u32 {
+        match self.types[&id].operands.first() {
+            Some(Operand::LiteralInt32(value)) => *value,
+            _ => 0,
+        }
+    }
+
+    fn count(&self, id: Word) -> u32 {
+        match self.types[&id].operands.get(1) {
+            Some(Operand::LiteralInt32(count)) => *count,
+            _ => 0,
+        }
+    }
+
+    /// Return the size and alignment of a type in bytes.
+    fn layout(&self, id: Word) -> (u32, u32) {
+        match self.opcode(id) {
+            Some(Op::TypeInt | Op::TypeFloat) => {
+                let size = self.literal(id) / 8;
+                (size, size)
+            }
+            Some(Op::TypeBool) => (4, 4),
+            Some(Op::TypeVector | Op::TypeMatrix) => {
+                let (size, align) = self.layout(self.id_operands(id)[0]);
+                (size * self.count(id), align)
+            }
+            Some(Op::TypeArray) => {
+                let [element, length] = self.id_operands(id)[..] else {
+                    return (0, 1);
+                };
+                let (size, align) = self.layout(element);
+                let stride = self.array_strides.get(&id).copied().unwrap_or(size);
+                (
+                    stride * self.constants.get(&length).copied().unwrap_or(0),
+                    align,
+                )
+            }
+            Some(Op::TypeRuntimeArray) => (0, self.layout(self.id_operands(id)[0]).1),
+            Some(Op::TypeStruct) => {
+                let mut end = 0;
+                let mut align = 1;
+                for (i, member) in self.id_operands(id).into_iter().enumerate() {
+                    let (member_size, member_align) = self.layout(member);
+                    let offset = self.offset(id, i as u32, end, member_align);
+                    end = end.max(offset + member_size);
+                    align = align.max(member_align);
+                }
+                (round_up(end, align), align)
+            }
+            _ => (0, 1),
+        }
+    }
+
+    /// Return the offset of a struct member, falling back to the next aligned offset if undecorated.
+    fn offset(&self, id: Word, member: u32, end: u32, align: u32) -> u32 {
+        self.member_offsets
+            .get(&(id, member))
+            .copied()
+            .unwrap_or_else(|| round_up(end, align))
+    }
+
+    /// Return the Rust type mirroring a SPIR-V type.
+    fn rust_type(&self, id: Word, struct_names: &BTreeMap<Word, String>) -> String {
+        match self.opcode(id) {
+            Some(Op::TypeInt) => format!(
+                "{}{}",
+                if self.is_signed(id) { "i" } else { "u" },
+                self.literal(id)
+            ),
+            Some(Op::TypeFloat) => format!("f{}", self.literal(id)),
+            Some(Op::TypeBool) => "u32".to_string(),
+            Some(Op::TypeVector | Op::TypeMatrix) => format!(
+                "[{}; {}]",
+                self.rust_type(self.id_operands(id)[0], struct_names),
+                self.count(id)
+            ),
+            Some(Op::TypeArray) => {
+                let [element, length] = self.id_operands(id)[..] else {
+                    return "()".to_string();
+                };
+                let length = self.constants.get(&length).copied().unwrap_or(0);
+                let (size, _) = self.layout(element);
+                match self.array_strides.get(&id) {
+                    // Elements padded out to their stride can't be represented directly
+                    Some(stride) if *stride != size => format!("[[u8; {stride}]; {length}]"),
+                    _ => format!("[{}; {length}]", self.rust_type(element, struct_names)),
+                }
+            }
+            Some(Op::TypeRuntimeArray) => format!(
+                "[{}; 0]",
+                self.rust_type(self.id_operands(id)[0], struct_names)
+            ),
+            Some(Op::TypeStruct) => struct_names[&id].clone(),
+            _ => "()".to_string(),
+        }
+    }
+
+    /// Return the GLSL-style name of a SPIR-V type, ex. `vec4` or `mat4`, or its struct name.
+    ///
+    /// Unnamed structs and types without a GLSL equivalent are named by their ID, ex. `%12`.
+    fn glsl_type(&self, id: Word) -> String {
+        let scalar_prefix = |id: Word| match self.opcode(id) {
+            Some(Op::TypeFloat) if self.literal(id) == 64 => "d",
+            Some(Op::TypeFloat) => "",
+            Some(Op::TypeInt) if self.is_signed(id) => "i",
+            Some(Op::TypeInt) => "u",
+            Some(Op::TypeBool) => "b",
+            _ => "",
+        };
+
+        match self.opcode(id) {
+            Some(Op::TypeBool) => "bool".to_string(),
+            Some(Op::TypeFloat) => match self.literal(id) {
+                32 => "float".to_string(),
+                64 => "double".to_string(),
+                width => format!("float{width}_t"),
+            },
+            Some(Op::TypeInt) => match (self.is_signed(id), self.literal(id)) {
+                (true, 32) => "int".to_string(),
+                (false, 32) => "uint".to_string(),
+                (true, width) => format!("int{width}_t"),
+                (false, width) => format!("uint{width}_t"),
+            },
+            Some(Op::TypeVector) => {
+                let component = self.id_operands(id)[0];
+                format!("{}vec{}", scalar_prefix(component), self.count(id))
+            }
+            Some(Op::TypeMatrix) => {
+                let column = self.id_operands(id)[0];
+                let component = self.id_operands(column)[0];
+                let (columns, rows) = (self.count(id), self.count(column));
+                if columns == rows {
+                    format!("{}mat{columns}", scalar_prefix(component))
+                } else {
+                    format!("{}mat{columns}x{rows}", scalar_prefix(component))
+                }
+            }
+            Some(Op::TypeArray) => {
+                let [element, length] = self.id_operands(id)[..] else {
+                    return format!("%{id}");
+                };
+                let length = self.constants.get(&length).copied().unwrap_or(0);
+                format!("{}[{length}]", self.glsl_type(element))
+            }
+            Some(Op::TypeRuntimeArray) => format!("{}[]", self.glsl_type(self.id_operands(id)[0])),
+            Some(Op::TypeStruct) => self
+                .names
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| format!("%{id}")),
+            _ => format!("%{id}"),
+        }
+    }
+
+    fn is_signed(&self, id: Word) -> bool {
+        matches!(
+            self.types[&id].operands.get(1),
+            Some(Operand::LiteralInt32(1))
+        )
+    }
+
+    /// Describe the struct `id`: its name, size, and the type, offset and layout decorations of each member.
+    fn struct_type(&self, id: Word) -> StructType {
+        let mut end = 0;
+        let members = self
+            .id_operands(id)
+            .into_iter()
+            .enumerate()
+            .map(|(i, member)| {
+                let i = i as u32;
+                let (member_size, member_align) = self.layout(member);
+                let offset = self.offset(id, i, end, member_align);
+                end = end.max(offset + member_size);
+
+                StructMember {
+                    name: self.member_names.get(&(id, i)).cloned(),
+                    ty: self.glsl_type(member),
+                    offset,
+                    array_stride: self.array_strides.get(&member).copied(),
+                    matrix_stride: self.member_matrix_strides.get(&(id, i)).copied(),
+                    row_major: self.row_major_members.contains(&(id, i)),
+                }
+            })
+            .collect();
+
+        StructType {
+            name: self.glsl_type(id),
+            size: self.layout(id).0,
+            members,
+        }
+    }
+
+    /// Return the Vulkan descriptor type of a resource variable in `storage_class` pointing to
+    /// `pointee`, as the lowercase `VkDescriptorType` name, and its descriptor count.
+    ///
+    /// Runtime arrays have no fixed count.
+    fn descriptor_type(
+        &self,
+        storage_class: StorageClass,
+        pointee: Word,
+    ) -> (&'static str, Option<u32>) {
+        let (element, count) = match self.opcode(pointee) {
+            Some(Op::TypeArray) => match self.id_operands(pointee)[..] {
+                [element, length] => (element, self.constants.get(&length).copied()),
+                _ => (pointee, Some(1)),
+            },
+            Some(Op::TypeRuntimeArray) => (self.id_operands(pointee)[0], None),
+            _ => (pointee, Some(1)),
+        };
+
+        let descriptor_type = match (storage_class, self.opcode(element)) {
+            (StorageClass::StorageBuffer, _) => "storage_buffer",
+            (StorageClass::Uniform, _) if self.buffer_blocks.contains(&element) => "storage_buffer",
+            (StorageClass::Uniform, _) => "uniform_buffer",
+            (_, Some(Op::TypeSampler)) => "sampler",
+            (_, Some(Op::TypeSampledImage)) => "combined_image_sampler",
+            (_, Some(Op::TypeImage)) => match self.types[&element].operands.as_slice() {
+                [_, Operand::Dim(Dim::DimSubpassData), ..] => "input_attachment",
+                [_, Operand::Dim(Dim::DimBuffer), _, _, _, Operand::LiteralInt32(2), ..] => {
+                    "storage_texel_buffer"
+                }
+                [_, Operand::Dim(Dim::DimBuffer), ..] => "uniform_texel_buffer",
+                [_, _, _, _, _, Operand::LiteralInt32(2), ..] => "storage_image",
+                _ => "sampled_image",
+            },
+            (_, Some(Op::TypeAccelerationStructureNV)) => "acceleration_structure",
+            _ => "unknown",
+        };
+
+        (descriptor_type, count)
+    }
+
+    /// Collect `id` and any structs nested within it, dependencies first.
+    fn collect_structs(&self, id: Word, out: &mut Vec<Word>) {
+        if out.contains(&id) {
+            return;
+        }
+
+        match self.opcode(id) {
+            Some(Op::TypeStruct) => {
+                for member in self.id_operands(id) {
+                    self.collect_structs(member, out);
+                }
+                out.push(id);
+            }
+            Some(Op::TypeArray | Op::TypeRuntimeArray | Op::TypeVector | Op::TypeMatrix) => {
+                self.collect_structs(self.id_operands(id)[0], out);
+            }
+            _ => (),
+        }
+    }
+
+    /// Generate a `#[repr(C)]` struct mirroring the SPIR-V struct `id`.
+    fn rust_struct(&self, id: Word, struct_names: &BTreeMap<Word, String>) -> String {
+        let name = &struct_names[&id];
+        let spirv_name = self
+            .names
+            .get(&id)
+            .map(String::as_str)
+            .unwrap_or("<unnamed>");
+        let (size, align) = self.layout(id);
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "/// Mirrors SPIR-V struct `{spirv_name}` ({size} bytes)."
+        )
+        .unwrap();
+        writeln!(out, "#[repr(C)]").unwrap();
+        writeln!(
+            out,
+            "#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]"
+        )
+        .unwrap();
+        writeln!(out, "pub struct {name} {{").unwrap();
+
+        let mut end = 0;
+        let mut pad = 0;
+        for (i, member) in self.id_operands(id).into_iter().enumerate() {
+            let i = i as u32;
+            let (member_size, member_align) = self.layout(member);
+            let offset = self.offset(id, i, end, member_align);
+
+            if offset > end {
+                writeln!(out, "    pub _pad{pad}: [u8; {}],", offset - end).unwrap();
+                pad += 1;
+            }
+
+            let member_name = self.member_names.get(&(id, i));
+            let field = member_name
+                .map(|name| identifier(name, false))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("field{i}"));
+
+            writeln!(
+                out,
+                "    /// `{}` at offset {offset}.",
+                member_name.map(String::as_str).unwrap_or("<unnamed>")
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "    pub {field}: {},",
+                self.rust_type(member, struct_names)
+            )
+            .unwrap();
+
+            end = end.max(offset + member_size);
+        }
+
+        let padded = round_up(end, align);
+        if padded > end {
+            writeln!(out, "    pub _pad{pad}: [u8; {}],", padded - end).unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+/// A struct type of a module, as per `--print-types`.
+struct StructType {
+    name: String,
+    size: u32,
+    members: Vec<StructMember>,
+}
+
+struct StructMember {
+    name: Option<String>,
+    ty: String,
+    offset: u32,
+    array_stride: Option<u32>,
+    matrix_stride: Option<u32>,
+    row_major: bool,
+}
+
+impl StructType {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "size": self.size,
+            "members": self.members.iter().map(|member| serde_json::json!({
+                "name": member.name,
+                "type": member.ty,
+                "offset": member.offset,
+                "array_stride": member.array_stride,
+                "matrix_stride": member.matrix_stride,
+                "row_major": member.row_major,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl std::fmt::Display for StructType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "struct {} {{ // {} bytes", self.name, self.size)?;
+        for member in &self.members {
+            write!(f, "    offset={}", member.offset)?;
+            if let Some(stride) = member.array_stride {
+                write!(f, " array_stride={stride}")?;
+            }
+            if let Some(stride) = member.matrix_stride {
+                write!(f, " matrix_stride={stride}")?;
+            }
+            if member.row_major {
+                write!(f, " row_major")?;
+            }
+            writeln!(
+                f,
+                " {} {};",
+                member.ty,
+                member.name.as_deref().unwrap_or("<unnamed>")
+            )?;
+        }
+        write!(f, "}}")
+    }
+}
+
+fn round_up(value: u32, align: u32) -> u32 {
+    (value + align - 1) / align * align
+}
+
+/// Convert a SPIR-V name into a Rust identifier, in `UpperCamelCase` if `type_name` is set.
+fn identifier(name: &str, type_name: bool) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    if type_name {
+        ident = ident
+            .split('_')
+            .filter(|part| !part.is_empty())
+            .map(|part| part[..1].to_ascii_uppercase() + &part[1..])
+            .collect();
+    }
+
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "crate", "else", "enum", "fn", "for", "if", "impl", "in", "let",
+        "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct",
+        "super", "trait", "type", "use", "where", "while",
+    ];
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+
+    ident
+}
+
+/// Return the struct types of a SPIR-V module, in ID order.
+fn struct_types(bytes: &[u8]) -> Result<Vec<StructType>, ParseState> {
+    let module = rspirv::dr::load_bytes(bytes)?;
+    let reflection = Reflection::new(&module);
+
+    Ok(reflection
+        .types
+        .iter()
+        .filter(|(_, inst)| inst.class.opcode == Op::TypeStruct)
+        .map(|(id, _)| reflection.struct_type(*id))
+        .collect())
+}
+
+/// Render the struct types of a SPIR-V module with the offset and layout decorations of each member, ex.
+/// `struct Uniforms { // 128 bytes` followed by a line per member like `offset=0 matrix_stride=16 mat4 view;`.
+pub fn types_text(bytes: &[u8]) -> Result<String, ParseState> {
+    Ok(struct_types(bytes)?
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Describe the struct types of a SPIR-V module as JSON, for tooling.
+pub fn types_json(bytes: &[u8]) -> Result<serde_json::Value, ParseState> {
+    Ok(struct_types(bytes)?
+        .iter()
+        .map(StructType::to_json)
+        .collect())
+}
+
+/// The decorations applied to an ID or struct member, as per `--print-decorations`.
+struct Decorated {
+    kind: &'static str,
+    id: Word,
+    name: Option<String>,
+    member: Option<(u32, Option<String>)>,
+    decorations: Vec<(Decoration, Vec<Operand>)>,
+}
+
+impl Decorated {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kind": self.kind,
+            "id": self.id,
+            "name": self.name,
+            "member": self.member.as_ref().map(|(member, _)| member),
+            "member_name": self.member.as_ref().and_then(|(_, name)| name.as_ref()),
+            "decorations": self.decorations.iter().map(|(decoration, operands)| serde_json::json!({
+                "decoration": format!("{decoration:?}"),
+                "operands": operands.iter().map(|operand| match operand {
+                    Operand::LiteralInt32(value) => serde_json::json!(value),
+                    operand => serde_json::json!(operand.to_string()),
+                }).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl std::fmt::Display for Decorated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match &self.name {
+            Some(name) => format!("'{name}'"),
+            None => format!("%{}", self.id),
+        };
+        match &self.member {
+            Some((_, Some(member_name))) => write!(f, "Member {name}.{member_name}")?,
+            Some((member, None)) => write!(f, "Member {name}.{member}")?,
+            None => write!(f, "{} {name}", self.kind)?,
+        }
+
+        let decorations = self
+            .decorations
+            .iter()
+            .map(|(decoration, operands)| match operands.as_slice() {
+                [] => format!("{decoration:?}"),
+                operands => format!(
+                    "{decoration:?}={}",
+                    operands
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            })
+            .collect::<Vec<_>>();
+        write!(f, ": {}", decorations.join(", "))
+    }
+}
+
+/// Gather the `OpDecorate`, `OpDecorateString` and `OpMemberDecorate` instructions of a SPIR-V module,
+/// grouped by the ID or struct member they apply to, in ID order.
+fn decorations(bytes: &[u8]) -> Result<Vec<Decorated>, ParseState> {
+    let module = rspirv::dr::load_bytes(bytes)?;
+    let reflection = Reflection::new(&module);
+
+    let variables = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::Variable)
+        .filter_map(|inst| inst.result_id)
+        .collect::<BTreeSet<_>>();
+    let functions = module
+        .functions
+        .iter()
+        .filter_map(|function| function.def.as_ref()?.result_id)
+        .collect::<BTreeSet<_>>();
+    let kind = |id: Word| {
+        if variables.contains(&id) {
+            "Variable"
+        } else if functions.contains(&id) {
+            "Function"
+        } else if reflection.opcode(id) == Some(Op::TypeStruct) {
+            "Struct"
+        } else if reflection.types.contains_key(&id) {
+            "Type"
+        } else {
+            "Id"
+        }
+    };
+
+    let mut grouped = BTreeMap::<(Word, Option<u32>), Vec<(Decoration, Vec<Operand>)>>::new();
+    for inst in &module.annotations {
+        match (inst.class.opcode, inst.operands.as_slice()) {
+            (
+                Op::Decorate | Op::DecorateString,
+                [Operand::IdRef(id), Operand::Decoration(decoration), operands @ ..],
+            ) => grouped
+                .entry((*id, None))
+                .or_default()
+                .push((*decoration, operands.to_vec())),
+            (
+                Op::MemberDecorate | Op::MemberDecorateString,
+                [Operand::IdRef(id), Operand::LiteralInt32(member), Operand::Decoration(decoration), operands @ ..],
+            ) => grouped
+                .entry((*id, Some(*member)))
+                .or_default()
+                .push((*decoration, operands.to_vec())),
+            _ => (),
+        }
+    }
+
+    Ok(grouped
+        .into_iter()
+        .map(|((id, member), decorations)| Decorated {
+            kind: kind(id),
+            id,
+            name: reflection.names.get(&id).cloned(),
+            member: member
+                .map(|member| (member, reflection.member_names.get(&(id, member)).cloned())),
+            decorations,
+        })
+        .collect())
+}
+
+/// Render the decorations of a SPIR-V module, a line per ID or struct member,
+/// ex. `Variable 'mvp_matrix': DescriptorSet=0, Binding=1`.
+pub fn decorations_text(bytes: &[u8]) -> Result<String, ParseState> {
+    Ok(decorations(bytes)?
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Describe the decorations of a SPIR-V module as JSON, for tooling.
+pub fn decorations_json(bytes: &[u8]) -> Result<serde_json::Value, ParseState> {
+    Ok(decorations(bytes)?.iter().map(Decorated::to_json).collect())
+}
+
+/// A global variable of a module, as per `--print-globals`.
+struct Global {
+    id: Word,
+    name: Option<String>,
+    storage_class: StorageClass,
+    ty: String,
+    descriptor_set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: Option<BuiltIn>,
+}
+
+impl Global {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "storage_class": format!("{:?}", self.storage_class),
+            "type": self.ty,
+            "descriptor_set": self.descriptor_set,
+            "binding": self.binding,
+            "location": self.location,
+            "built_in": self.built_in.map(|built_in| format!("{built_in:?}")),
+        })
+    }
+}
+
+impl std::fmt::Display for Global {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{:?} {} {name}", self.storage_class, self.ty)?,
+            None => write!(f, "{:?} {} %{}", self.storage_class, self.ty, self.id)?,
+        }
+
+        let mut decorations = vec![];
+        if let Some(set) = self.descriptor_set {
+            decorations.push(format!("set={set}"));
+        }
+        if let Some(binding) = self.binding {
+            decorations.push(format!("binding={binding}"));
+        }
+        if let Some(location) = self.location {
+            decorations.push(format!("location={location}"));
+        }
+        if let Some(built_in) = self.built_in {
+            decorations.push(format!("built_in={built_in:?}"));
+        }
+
+        if !decorations.is_empty() {
+            write!(f, " ({})", decorations.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Gather the global variables of a SPIR-V module, in declaration order.
+fn globals(bytes: &[u8]) -> Result<Vec<Global>, ParseState> {
+    let module = rspirv::dr::load_bytes(bytes)?;
+    let reflection = Reflection::new(&module);
+
+    Ok(module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::Variable)
+        .filter_map(|inst| {
+            let id = inst.result_id?;
+            let Some(Operand::StorageClass(storage_class)) = inst.operands.first() else {
+                return None;
+            };
+            let ty = match reflection
+                .types
+                .get(&inst.result_type?)?
+                .operands
+                .as_slice()
+            {
+                [Operand::StorageClass(_), Operand::IdRef(pointee)] => {
+                    reflection.glsl_type(*pointee)
+                }
+                _ => return None,
+            };
+
+            Some(Global {
+                id,
+                name: reflection.names.get(&id).cloned(),
+                storage_class: *storage_class,
+                ty,
+                descriptor_set: reflection.descriptor_sets.get(&id).copied(),
+                binding: reflection.bindings.get(&id).copied(),
+                location: reflection.locations.get(&id).copied(),
+                built_in: reflection.built_ins.get(&id).copied(),
+            })
+        })
+        .collect())
+}
+
+/// Render the global variables of a SPIR-V module, a line per variable with its storage class, type,
+/// name and interface decorations, ex. `Uniform Camera camera (set=0, binding=1)`.
+pub fn globals_text(bytes: &[u8]) -> Result<String, ParseState> {
+    Ok(globals(bytes)?
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Describe the global variables of a SPIR-V module as JSON, for tooling.
+pub fn globals_json(bytes: &[u8]) -> Result<serde_json::Value, ParseState> {
+    Ok(globals(bytes)?.iter().map(Global::to_json).collect())
+}
+
+/// Return the capabilities declared by the given SPIR-V modules, named by their `Debug` representation.
+pub fn capabilities<'a>(
+    modules: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<BTreeSet<String>, ParseState> {
+    let mut capabilities = BTreeSet::new();
+    for bytes in modules {
+        let module = rspirv::dr::load_bytes(bytes)?;
+        for inst in &module.capabilities {
+            if let [Operand::Capability(capability)] = inst.operands.as_slice() {
+                capabilities.insert(format!("{capability:?}"));
+            }
+        }
+    }
+
+    Ok(capabilities)
+}
+
+/// Return the name and execution model of each entry point in a SPIR-V module.
+pub fn entry_points(bytes: &[u8]) -> Result<Vec<(String, ExecutionModel)>, ParseState> {
+    let module = rspirv::dr::load_bytes(bytes)?;
+
+    Ok(module
+        .entry_points
+        .iter()
+        .filter_map(|inst| match inst.operands.as_slice() {
+            [Operand::ExecutionModel(model), Operand::IdRef(_), Operand::LiteralString(name), ..] => {
+                Some((name.clone(), *model))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// Generate Rust source containing `#[repr(C)]` mirrors of the structs used by
+/// `Uniform` and `PushConstant` variables in the given SPIR-V modules.
+///
+/// Structs with the same name in several modules are emitted once.
+pub fn bindings<'a>(modules: impl IntoIterator<Item = &'a [u8]>) -> Result<String, ParseState> {
+    let mut structs = BTreeMap::<String, String>::new();
+
+    for bytes in modules {
+        let module = rspirv::dr::load_bytes(bytes)?;
+        let reflection = Reflection::new(&module);
+
+        let mut ids = vec![];
+        for id in reflection.block_structs(&module) {
+            reflection.collect_structs(id, &mut ids);
+        }
+
+        let mut used = BTreeSet::new();
+        let mut struct_names = BTreeMap::new();
+        for id in &ids {
+            let mut name = reflection
+                .names
+                .get(id)
+                .map(|name| identifier(name, true))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("Struct{id}"));
+
+            if !used.insert(name.clone()) {
+                name = format!("{name}{id}");
+                used.insert(name.clone());
+            }
+
+            struct_names.insert(*id, name);
+        }
+
+        for id in ids {
+            structs
+                .entry(struct_names[&id].clone())
+                .or_insert_with(|| reflection.rust_struct(id, &struct_names));
+        }
+    }
+
+    let mut out = String::from("// Generated by rust-gpu-builder. Do not edit.\n");
+    out += "//\n";
+    out += "// Arrays longer than 32 elements require bytemuck's `min_const_generics` feature.\n";
+    for source in structs.values() {
+        out += "\n";
+        out += source;
+    }
+
+    Ok(out)
+}
+
+/// Return the Vulkan shader stage flag of an execution model, and its bit.
+fn stage_flag(model: ExecutionModel) -> Option<(&'static str, u32)> {
+    Some(match model {
+        ExecutionModel::Vertex => ("VK_SHADER_STAGE_VERTEX_BIT", 0x1),
+        ExecutionModel::TessellationControl => ("VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT", 0x2),
+        ExecutionModel::TessellationEvaluation => {
+            ("VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT", 0x4)
+        }
+        ExecutionModel::Geometry => ("VK_SHADER_STAGE_GEOMETRY_BIT", 0x8),
+        ExecutionModel::Fragment => ("VK_SHADER_STAGE_FRAGMENT_BIT", 0x10),
+        ExecutionModel::GLCompute => ("VK_SHADER_STAGE_COMPUTE_BIT", 0x20),
+        ExecutionModel::TaskNV => ("VK_SHADER_STAGE_TASK_BIT_NV", 0x40),
+        ExecutionModel::MeshNV => ("VK_SHADER_STAGE_MESH_BIT_NV", 0x80),
+        ExecutionModel::RayGenerationNV => ("VK_SHADER_STAGE_RAYGEN_BIT_KHR", 0x100),
+        ExecutionModel::AnyHitNV => ("VK_SHADER_STAGE_ANY_HIT_BIT_KHR", 0x200),
+        ExecutionModel::ClosestHitNV => ("VK_SHADER_STAGE_CLOSEST_HIT_BIT_KHR", 0x400),
+        ExecutionModel::MissNV => ("VK_SHADER_STAGE_MISS_BIT_KHR", 0x800),
+        ExecutionModel::IntersectionNV => ("VK_SHADER_STAGE_INTERSECTION_BIT_KHR", 0x1000),
+        ExecutionModel::CallableNV => ("VK_SHADER_STAGE_CALLABLE_BIT_KHR", 0x2000),
+        _ => return None,
+    })
+}
+
+/// Bits of the graphics stages, as combined by `VK_SHADER_STAGE_ALL_GRAPHICS`.
+const ALL_GRAPHICS: u32 = 0x1f;
+
+/// A descriptor binding, and the entry points using it across all modules.
+struct DescriptorBinding {
+    name: Option<String>,
+    descriptor_type: &'static str,
+    count: Option<u32>,
+    entry_points: BTreeSet<String>,
+    stages: BTreeSet<&'static str>,
+    stage_bits: u32,
+}
+
+/// Return the global variables used by an entry point: those listed in its interface, and those
+/// referred to by any function reachable from it.
+fn used_variables(module: &Module, entry: &Instruction) -> BTreeSet<Word> {
+    let ids = |operands: &[Operand]| {
+        operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::IdRef(id) => Some(*id),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let Some(Operand::IdRef(function)) = entry.operands.get(1) else {
+        return BTreeSet::new();
+    };
+
+    // Interfaces only list every global variable from SPIR-V 1.4 onwards, so also walk the functions
+    let reachable = transform::reachable_functions(module, *function);
+    let mut used = ids(&entry.operands[2..])
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    for function in &module.functions {
+        if !transform::function_id(function).map_or(false, |id| reachable.contains(&id)) {
+            continue;
+        }
+
+        for inst in function.blocks.iter().flat_map(|block| &block.instructions) {
+            used.extend(ids(&inst.operands));
+        }
+    }
+
+    used
+}
+
+/// Describe the descriptor sets of the given SPIR-V modules as JSON, listing each binding's
+/// descriptor type, count, and the entry points and stages using it.
+///
+/// Each binding gets a recommended `VkDescriptorSetLayoutBinding::stageFlags`, restricted to the
+/// stages that use it: ex. `VK_SHADER_STAGE_FRAGMENT_BIT` for a binding only used by fragment
+/// shaders, or `VK_SHADER_STAGE_ALL_GRAPHICS` for one shared by several graphics stages.
+/// Bindings with the same set and number in several modules are merged.
+pub fn descriptor_sets<'a>(
+    modules: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<serde_json::Value, ParseState> {
+    let mut bindings = BTreeMap::<(u32, u32), DescriptorBinding>::new();
+
+    for bytes in modules {
+        let module = rspirv::dr::load_bytes(bytes)?;
+        let reflection = Reflection::new(&module);
+
+        let mut variables = BTreeMap::new();
+        for inst in &module.types_global_values {
+            let (Some(id), Some(Operand::StorageClass(storage_class))) =
+                (inst.result_id, inst.operands.first())
+            else {
+                continue;
+            };
+            let (Some(set), Some(binding)) = (
+                reflection.descriptor_sets.get(&id),
+                reflection.bindings.get(&id),
+            ) else {
+                continue;
+            };
+            let pointee = inst
+                .result_type
+                .and_then(|pointer| reflection.types.get(&pointer))
+                .and_then(|pointer| match pointer.operands.as_slice() {
+                    [Operand::StorageClass(_), Operand::IdRef(pointee)] => Some(*pointee),
+                    _ => None,
+                });
+            let Some(pointee) = pointee else {
+                continue;
+            };
+
+            let (descriptor_type, count) = reflection.descriptor_type(*storage_class, pointee);
+            let name = reflection
+                .names
+                .get(&id)
+                .or_else(|| reflection.names.get(&pointee))
+                .cloned();
+
+            bindings
+                .entry((*set, *binding))
+                .or_insert_with(|| DescriptorBinding {
+                    name,
+                    descriptor_type,
+                    count,
+                    entry_points: BTreeSet::new(),
+                    stages: BTreeSet::new(),
+                    stage_bits: 0,
+                });
+            variables.insert(id, (*set, *binding));
+        }
+
+        for entry in &module.entry_points {
+            let [Operand::ExecutionModel(model), _, Operand::LiteralString(name), ..] =
+                entry.operands.as_slice()
+            else {
+                continue;
+            };
+
+            for id in used_variables(&module, entry) {
+                let Some(key) = variables.get(&id) else {
+                    continue;
+                };
+                let binding = bindings.get_mut(key).unwrap();
+                binding.entry_points.insert(name.clone());
+                if let Some((flag, bit)) = stage_flag(*model) {
+                    binding.stages.insert(flag);
+                    binding.stage_bits |= bit;
+                }
+            }
+        }
+    }
+
+    let mut sets = BTreeMap::<u32, Vec<serde_json::Value>>::new();
+    for ((set, number), binding) in bindings {
+        let shared_graphics = binding.stages.len() > 1 && binding.stage_bits & !ALL_GRAPHICS == 0;
+        let (stage_flags, stage_bits) = if shared_graphics {
+            ("VK_SHADER_STAGE_ALL_GRAPHICS".to_string(), ALL_GRAPHICS)
+        } else {
+            (
+                binding
+                    .stages
+                    .iter()
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+                binding.stage_bits,
+            )
+        };
+
+        sets.entry(set).or_default().push(serde_json::json!({
+            "binding": number,
+            "name": binding.name,
+            "descriptor_type": binding.descriptor_type,
+            "count": binding.count,
+            "entry_points": binding.entry_points,
+            "stage_flags": (stage_bits != 0).then_some(stage_flags),
+            "stage_flags_bits": stage_bits,
+        }));
+    }
+
+    Ok(serde_json::json!({
+        "sets": sets
+            .into_iter()
+            .map(|(set, bindings)| serde_json::json!({ "set": set, "bindings": bindings }))
+            .collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,130 @@
+//! Shader crates fetched from git, as per `git+<url>#<rev>` crate paths.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::source_hash::to_hex;
+
+/// A shader crate in a git repository, optionally pinned to a branch, tag or commit.
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    url: String,
+    rev: Option<String>,
+}
+
+impl GitSource {
+    /// Parse a `git+<url>[#<rev>]` crate path.
+    ///
+    /// Returns `None` for local paths.
+    pub fn parse(path: &Path) -> Option<Self> {
+        let spec = path.to_str()?.strip_prefix("git+")?;
+        let (url, rev) = match spec.split_once('#') {
+            Some((url, rev)) => (url, Some(rev.to_string())),
+            None => (spec, None),
+        };
+
+        Some(GitSource {
+            url: url.to_string(),
+            rev,
+        })
+    }
+
+    /// The directory the repository is cloned into, unique to its URL and revision.
+    ///
+    /// Stored under the builder's own build directory.
+    pub fn checkout_dir(&self) -> PathBuf {
+        let name = self
+            .url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(".git");
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.url);
+        if let Some(rev) = &self.rev {
+            hasher.update(b"#");
+            hasher.update(rev);
+        }
+        let hash = to_hex(&hasher.finalize());
+
+        PathBuf::from(env!("OUT_DIR"))
+            .join("git")
+            .join(format!("{name}-{}", &hash[..12]))
+    }
+
+    /// Clone the repository if it isn't cached, or update the cached clone if `refresh` is set.
+    ///
+    /// Returns the path of the clone.
+    pub fn fetch(&self, refresh: bool) -> io::Result<PathBuf> {
+        let dir = self.checkout_dir();
+
+        let cloned = dir.join(".git").exists();
+        if cloned && !refresh {
+            info!("Using cached clone of {} in {dir:?}", self.url);
+            return Ok(dir);
+        }
+
+        if cloned {
+            info!("Refreshing clone of {} in {dir:?}...", self.url);
+        } else {
+            info!("Cloning {} into {dir:?}...", self.url);
+            std::fs::create_dir_all(&dir)?;
+            git(Command::new("git").arg("clone").arg(&self.url).arg(&dir))?;
+        }
+
+        match &self.rev {
+            // Fetching the revision resolves branches and tags to their latest commit
+            Some(rev) => {
+                git(Command::new("git")
+                    .arg("-C")
+                    .arg(&dir)
+                    .args(["fetch", "origin", rev]))?;
+                git(Command::new("git").arg("-C").arg(&dir).args([
+                    "checkout",
+                    "--detach",
+                    "FETCH_HEAD",
+                ]))?;
+            }
+            None if cloned => {
+                git(Command::new("git")
+                    .arg("-C")
+                    .arg(&dir)
+                    .args(["pull", "--ff-only"]))?;
+            }
+            None => (),
+        }
+
+        Ok(dir)
+    }
+}
+
+impl std::fmt::Display for GitSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.url)?;
+        if let Some(rev) = &self.rev {
+            write!(f, "#{rev}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a git command, failing if it exits unsuccessfully.
+fn git(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{command:?} exited with {status}"),
+        ))
+    }
+}
@@ -0,0 +1,192 @@
+//! Machine-readable build reports for CI systems.
+
+use std::{fmt::Write, path::Path, time::Duration};
+
+use serde_json::Value;
+
+use crate::{
+    diagnostics::{self, Diagnostic},
+    error::BuildError,
+};
+
+/// Escape text for inclusion in XML attributes and content.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Describe a build failure in full, including any diagnostics from the `cargo check` pre-pass.
+fn failure_details(e: &BuildError) -> String {
+    let mut details = e.chain();
+
+    if let BuildError::CheckFailed(diagnostics) = e {
+        for diagnostic in diagnostics {
+            details += "\n\n";
+            match &diagnostic.rendered {
+                Some(rendered) => details += rendered.trim_end(),
+                None => details += &format!("{}: {}", diagnostic.level, diagnostic.message),
+            }
+        }
+    }
+
+    details
+}
+
+/// Render the outcome of a build as a JUnit XML report.
+///
+/// The shader crate is reported as a test case, along with each of its entry points for
+/// multi-module builds. A failed build is reported as a single failing test case.
+pub fn junit(
+    crate_name: &str,
+    entry_points: Result<&[String], &BuildError>,
+    multimodule: bool,
+    time: Duration,
+) -> String {
+    let crate_name = xml_escape(crate_name);
+    let time = time.as_secs_f64();
+
+    let mut cases = vec![];
+    let mut failures = 0;
+    match entry_points {
+        Ok(entry_points) => {
+            cases.push(format!(
+                r#"    <testcase classname="{crate_name}" name="{crate_name}" time="{time:.3}"/>"#
+            ));
+
+            if multimodule {
+                for entry_point in entry_points {
+                    cases.push(format!(
+                        r#"    <testcase classname="{crate_name}" name="{}" time="{time:.3}"/>"#,
+                        xml_escape(entry_point)
+                    ));
+                }
+            }
+        }
+        Err(e) => {
+            failures += 1;
+            cases.push(format!(
+                "    <testcase classname=\"{crate_name}\" name=\"{crate_name}\" time=\"{time:.3}\">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                xml_escape(&e.to_string()),
+                xml_escape(&failure_details(e))
+            ));
+        }
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        r#"<testsuites name="rust-gpu-builder" tests="{}" failures="{failures}" time="{time:.3}">"#,
+        cases.len()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        r#"  <testsuite name="{crate_name}" tests="{}" failures="{failures}" time="{time:.3}">"#,
+        cases.len()
+    )
+    .unwrap();
+    for case in cases {
+        writeln!(out, "{case}").unwrap();
+    }
+    out += "  </testsuite>\n";
+    out += "</testsuites>\n";
+
+    out
+}
+
+/// A SARIF `artifactLocation` for `path`, using forward slashes as per the URI syntax.
+fn sarif_artifact(path: &Path) -> Value {
+    serde_json::json!({ "uri": path.to_string_lossy().replace('\\', "/") })
+}
+
+/// Convert a compiler diagnostic to a SARIF result.
+///
+/// Returns `None` for levels without a SARIF equivalent.
+fn sarif_result(diagnostic: &Diagnostic, crate_path: &Path) -> Option<Value> {
+    let level = match diagnostic.level.as_str() {
+        "error" | "error: internal compiler error" => "error",
+        "warning" => "warning",
+        "note" | "help" => "note",
+        _ => return None,
+    };
+
+    let locations = diagnostic
+        .spans
+        .iter()
+        .filter(|span| span.is_primary)
+        .map(|span| {
+            serde_json::json!({
+                "physicalLocation": {
+                    "artifactLocation": sarif_artifact(&span.path(crate_path)),
+                    "region": {
+                        "startLine": span.line_start,
+                        "endLine": span.line_end,
+                        "startColumn": span.column_start,
+                        "endColumn": span.column_end,
+                    },
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Some(serde_json::json!({
+        "ruleId": diagnostic.code.as_deref().unwrap_or("rustc"),
+        "level": level,
+        "message": { "text": diagnostic.message },
+        "locations": locations,
+    }))
+}
+
+/// Render the diagnostics of a build as a SARIF 2.1.0 log.
+///
+/// Diagnostics are those of the `cargo check` pre-pass. A `failure` other than the pre-pass
+/// failing is reported as a single error result, located at the shader crate's manifest.
+pub fn sarif(
+    crate_path: &Path,
+    diagnostics: &[Diagnostic],
+    failure: Option<&BuildError>,
+) -> String {
+    let mut results = diagnostics
+        .iter()
+        .filter_map(|diagnostic| sarif_result(diagnostic, crate_path))
+        .collect::<Vec<_>>();
+
+    if let Some(e) = failure {
+        let manifest = diagnostics::workspace_relative(crate_path.join("Cargo.toml"));
+        results.push(serde_json::json!({
+            "ruleId": "build-failed",
+            "level": "error",
+            "message": { "text": e.chain() },
+            "locations": [{
+                "physicalLocation": { "artifactLocation": sarif_artifact(&manifest) },
+            }],
+        }));
+    }
+
+    let sarif = serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/Bevy-Rust-GPU/rust-gpu-builder",
+                },
+            },
+            "results": results,
+        }],
+    });
+
+    serde_json::to_string_pretty(&sarif).expect("Failed to serialize SARIF log")
+}
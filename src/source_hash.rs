@@ -0,0 +1,229 @@
+//! Content hashing of shader sources, used to skip rebuilds when nothing has changed.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{filter::WatchFilter, paths};
+
+/// File in each cache entry holding the shader crate and target the entry belongs to.
+const KEY_FILE: &str = "key";
+/// File in each cache entry holding the source hash of the last successful build.
+const HASH_FILE: &str = "source-hash";
+/// File in each cache entry listing the files written by the last successful build, one per line.
+const OUTPUTS_FILE: &str = "outputs";
+
+/// Persistent store of the source hash of each shader crate's last successful build.
+///
+/// Each entry is a directory named after the hash of its crate, target and build options,
+/// so that it survives builder restarts and is shared between terminal sessions.
+#[derive(Debug, Clone)]
+pub struct SourceCache {
+    pub dir: PathBuf,
+    /// Total size in bytes above which the least recently used entries are evicted.
+    max_size: Option<u64>,
+}
+
+impl SourceCache {
+    pub fn new(dir: PathBuf, max_size: Option<u64>) -> Self {
+        SourceCache { dir, max_size }
+    }
+
+    /// The platform's default cache directory: `%APPDATA%\rust-gpu-builder` on Windows,
+    /// and `$XDG_CACHE_HOME/rust-gpu-builder` or `~/.cache/rust-gpu-builder` elsewhere.
+    ///
+    /// Falls back to the builder's own build directory if none is set.
+    pub fn default_dir() -> PathBuf {
+        let base = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        };
+
+        base.unwrap_or_else(|| PathBuf::from(env!("OUT_DIR")))
+            .join("rust-gpu-builder")
+    }
+
+    /// The entry directory for `key` built with `options`.
+    fn entry(&self, key: &str, options: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(key.as_bytes());
+        hasher.update([0]);
+        hasher.update(options.as_bytes());
+        self.dir.join(&to_hex(&hasher.finalize())[..16])
+    }
+
+    /// Returns true if `hash` matches the last successful build of `key` with `options`,
+    /// and the files it wrote still exist.
+    ///
+    /// A hit marks the entry as recently used.
+    pub fn is_fresh(&self, key: &str, options: &str, hash: &str) -> bool {
+        let entry = self.entry(key, options);
+        let path = entry.join(HASH_FILE);
+        // Entries without a list of outputs predate it, so can't vouch for them
+        let fresh = fs::read_to_string(&path).map_or(false, |cached| cached == hash)
+            && fs::read_to_string(entry.join(OUTPUTS_FILE)).map_or(false, |outputs| {
+                outputs.lines().all(|output| Path::new(output).exists())
+            });
+        if fresh {
+            // Rewriting the file bumps its modification time, which orders eviction
+            fs::write(&path, hash).ok();
+        }
+        fresh
+    }
+
+    /// Record `hash` as the inputs of the last successful build of `key` with `options`,
+    /// along with the `outputs` it wrote, then evict the least recently used entries
+    /// if the cache exceeds its maximum size.
+    pub fn update(
+        &self,
+        key: &str,
+        options: &str,
+        hash: String,
+        outputs: &[PathBuf],
+    ) -> io::Result<()> {
+        let entry = self.entry(key, options);
+        fs::create_dir_all(&entry)?;
+        fs::write(entry.join(KEY_FILE), key)?;
+        fs::write(
+            entry.join(OUTPUTS_FILE),
+            outputs
+                .iter()
+                .map(|output| output.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )?;
+        fs::write(entry.join(HASH_FILE), hash)?;
+
+        self.evict()
+    }
+
+    /// Delete every entry of `key`, regardless of build options, returning how many were deleted.
+    pub fn clear(&self, key: &str) -> io::Result<usize> {
+        let mut cleared = 0;
+        for entry in self.entries()? {
+            if fs::read_to_string(entry.join(KEY_FILE)).map_or(false, |other| other == key) {
+                fs::remove_dir_all(&entry)?;
+                cleared += 1;
+            }
+        }
+        Ok(cleared)
+    }
+
+    /// Total size of every entry in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        let mut size = 0;
+        for entry in self.entries()? {
+            for file in fs::read_dir(&entry)?.flatten() {
+                size += file.metadata()?.len();
+            }
+        }
+        Ok(size)
+    }
+
+    fn entries(&self) -> io::Result<Vec<PathBuf>> {
+        match fs::read_dir(&self.dir) {
+            Ok(entries) => Ok(entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete the least recently used entries until the cache fits in its maximum size.
+    fn evict(&self) -> io::Result<()> {
+        let Some(max_size) = self.max_size else {
+            return Ok(())
+        };
+
+        let mut entries = vec![];
+        for entry in self.entries()? {
+            let mut size = 0;
+            let mut used = SystemTime::UNIX_EPOCH;
+            for file in fs::read_dir(&entry)?.flatten() {
+                let metadata = file.metadata()?;
+                size += metadata.len();
+                used = used.max(metadata.modified()?);
+            }
+            entries.push((used, size, entry));
+        }
+
+        let mut total = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        entries.sort();
+        for (_, size, entry) in entries {
+            if total <= max_size {
+                break;
+            }
+            debug!("Evicting source cache entry {entry:?}");
+            fs::remove_dir_all(&entry)?;
+            total -= size;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash the path and contents of every file under `roots` accepted by `filter`, seeded with `salt`.
+///
+/// `target` and hidden directories are skipped. Filtering as the watcher does keeps files
+/// that never trigger a rebuild, such as the builder's own outputs, from changing the hash.
+pub fn hash_sources(roots: &[PathBuf], salt: &str, filter: &WatchFilter) -> io::Result<String> {
+    let mut files = Vec::new();
+    for root in roots {
+        // Root files are hashed regardless of the filter, as the watcher watches them regardless
+        let root = paths::absolute(root);
+        if root.is_dir() {
+            collect_files(&root, filter, &mut files)?;
+        } else {
+            files.push(root);
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&file)?);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn collect_files(dir: &Path, filter: &WatchFilter, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let skip = path
+            .file_name()
+            .map(|name| name == "target" || name.to_string_lossy().starts_with('.'))
+            .unwrap_or_default();
+
+        if skip {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_files(&path, filter, files)?;
+        } else if filter.matches(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Format `bytes` as a lowercase hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
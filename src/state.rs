@@ -0,0 +1,120 @@
+//! Build state persisted across restarts, as per `--persist-state`.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::Value;
+use tracing::{error, info};
+
+/// The outcome of the last build of a target.
+#[derive(Debug, Clone)]
+pub struct BuildState {
+    /// Source hash of the build's inputs, if the source cache is enabled.
+    pub source_hash: Option<String>,
+    /// Exit code the build would have produced as a one-shot build, or 0 if it succeeded.
+    pub exit_code: i32,
+    /// Unix time at which the build finished.
+    pub finished_at: u64,
+    /// Files written by the build.
+    pub outputs: Vec<PathBuf>,
+}
+
+/// Serializes updates to state files, as targets' outputs are written concurrently.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Load the state file at `path`, keyed by target.
+fn load_all(path: &Path) -> BTreeMap<String, Value> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+impl BuildState {
+    /// The state of a build finishing now.
+    pub fn new(source_hash: Option<String>, exit_code: i32, outputs: Vec<PathBuf>) -> Self {
+        BuildState {
+            source_hash,
+            exit_code,
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+            outputs,
+        }
+    }
+
+    /// Load the last build state of `target` from the state file at `path`.
+    pub fn load(path: &Path, target: &str) -> Option<Self> {
+        let state = load_all(path).remove(target)?;
+
+        Some(BuildState {
+            source_hash: state["source_hash"].as_str().map(str::to_string),
+            exit_code: state["exit_code"].as_i64()? as i32,
+            finished_at: state["finished_at"].as_u64()?,
+            outputs: state["outputs"]
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_str)
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+
+    /// Record this as the last build state of `target` in the state file at `path`.
+    pub fn save(&self, path: &Path, target: &str) -> io::Result<()> {
+        let _lock = STATE_LOCK.lock().unwrap();
+
+        let mut states = load_all(path);
+        states.insert(
+            target.to_string(),
+            serde_json::json!({
+                "source_hash": self.source_hash,
+                "exit_code": self.exit_code,
+                "finished_at": self.finished_at,
+                "outputs": self
+                    .outputs
+                    .iter()
+                    .map(|output| output.to_string_lossy())
+                    .collect::<Vec<_>>(),
+            }),
+        );
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(&states)?)
+    }
+
+    /// Returns true if this build's inputs match `source_hash` and its outputs still exist.
+    pub fn is_fresh(&self, source_hash: &str) -> bool {
+        self.source_hash.as_deref() == Some(source_hash)
+            && self.outputs.iter().all(|output| output.exists())
+    }
+
+    /// Report the outcome of this build.
+    pub fn log(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let ago = Duration::from_secs(now.saturating_sub(self.finished_at));
+
+        if self.exit_code == 0 {
+            info!("Sources unchanged since the last build {ago:.0?} ago, which succeeded");
+            for output in &self.outputs {
+                info!("Output: {output:?}");
+            }
+        } else {
+            error!(
+                "Sources unchanged since the last build {ago:.0?} ago, which failed with exit code {}",
+                self.exit_code
+            );
+        }
+    }
+}
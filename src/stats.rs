@@ -0,0 +1,243 @@
+//! Build statistics accumulated over a watch session.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+use serde_json::Value;
+use tracing::info;
+
+/// Module sizes in bytes, keyed by target and entry point or module name, as per [`crate::metrics::module_sizes`].
+pub type ModuleSizes = BTreeMap<(String, String), u64>;
+
+/// Label each module by name, qualified with its target if sizes were recorded for several targets.
+fn size_labels(sizes: &ModuleSizes) -> Vec<(String, u64)> {
+    let multi_target = sizes
+        .keys()
+        .any(|(target, _)| sizes.keys().any(|(other, _)| other != target));
+
+    sizes
+        .iter()
+        .map(|((target, name), size)| {
+            let label = if multi_target {
+                format!("{name} ({target})")
+            } else {
+                name.clone()
+            };
+            (label, *size)
+        })
+        .collect()
+}
+
+/// Running totals over every build performed this session.
+#[derive(Debug, Default, Clone)]
+pub struct BuildStats {
+    pub successes: usize,
+    pub failures: usize,
+    pub total_time: Duration,
+    pub min_time: Option<Duration>,
+    pub max_time: Option<Duration>,
+    /// Duration of the first build this session, reported in the summary.
+    pub first_time: Option<Duration>,
+    /// Duration of the most recent build.
+    pub last_time: Option<Duration>,
+    /// Builds skipped because the source cache reported their sources as unchanged.
+    pub cache_hits: usize,
+    /// Builds run because their sources weren't in the source cache.
+    pub cache_misses: usize,
+    /// Number of the most recent build of each crate and target whose outputs were written,
+    /// counting every build this session.
+    pub last_written: BTreeMap<String, usize>,
+}
+
+impl BuildStats {
+    /// Total number of builds, successful or otherwise.
+    pub fn builds(&self) -> usize {
+        self.successes + self.failures
+    }
+
+    /// Record the outcome of a single build.
+    pub fn record(&mut self, success: bool, duration: Duration) {
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+
+        self.first_time.get_or_insert(duration);
+        self.last_time = Some(duration);
+        self.total_time += duration;
+        self.min_time = Some(self.min_time.map_or(duration, |min| min.min(duration)));
+        self.max_time = Some(self.max_time.map_or(duration, |max| max.max(duration)));
+    }
+
+    /// Mean duration of all recorded builds.
+    pub fn average_time(&self) -> Option<Duration> {
+        let builds = u32::try_from(self.builds())
+            .ok()
+            .filter(|builds| *builds > 0)?;
+        Some(self.total_time / builds)
+    }
+
+    /// Record that the outputs of build number `build`, of the crate and target `name`, were written.
+    pub fn record_written(&mut self, name: &str, build: usize) {
+        self.last_written.insert(name.to_string(), build);
+    }
+
+    /// Record whether the source cache allowed a build to be skipped.
+    pub fn record_cache(&mut self, hit: bool) {
+        if hit {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
+        }
+    }
+
+    /// Build time saved by the source cache, estimated as one build of the most recent duration per hit.
+    pub fn cache_time_saved(&self) -> Duration {
+        let hits = u32::try_from(self.cache_hits).unwrap_or(u32::MAX);
+        self.last_time.unwrap_or_default() * hits
+    }
+
+    /// Log source cache statistics, with the cache's size on disk in bytes.
+    pub fn log_cache_stats(&self, cache_size: u64) {
+        info!(
+            "Source cache: {} builds, {} hits, {} misses, ~{:.2?} saved, {cache_size} bytes on disk",
+            self.builds() + self.cache_hits,
+            self.cache_hits,
+            self.cache_misses,
+            self.cache_time_saved()
+        );
+    }
+
+    /// Format source cache statistics as a JSON `cache_stats` event, for `--message-format json`.
+    pub fn cache_stats_json(&self, cache_size: u64) -> Value {
+        serde_json::json!({
+            "event": "cache_stats",
+            "builds": self.builds() + self.cache_hits,
+            "hits": self.cache_hits,
+            "misses": self.cache_misses,
+            "time_saved_ms": self.cache_time_saved().as_millis() as u64,
+            "size_bytes": cache_size,
+        })
+    }
+
+    /// Log a summary of the accumulated statistics, along with the latest size of each module.
+    pub fn log_summary(&self, sizes: &ModuleSizes) {
+        info!("Build statistics:");
+        info!(
+            "Builds: {} ({} succeeded, {} failed)",
+            self.builds(),
+            self.successes,
+            self.failures
+        );
+
+        if let (Some(avg), Some(min), Some(max)) =
+            (self.average_time(), self.min_time, self.max_time)
+        {
+            info!("Build time: avg {avg:.2?}, min {min:.2?}, max {max:.2?}");
+        }
+
+        if let Some(first) = self.first_time {
+            info!("Initial build time: {first:.2?}");
+        }
+
+        info!("Total time spent building: {:.2?}", self.total_time);
+
+        if !sizes.is_empty() {
+            info!("Output sizes:");
+            for (label, size) in size_labels(sizes) {
+                info!("  {label}: {size} bytes");
+            }
+        }
+    }
+
+    /// Log a one-line summary, for one-shot builds.
+    ///
+    /// Nothing is logged if every target was up to date.
+    pub fn log_line(&self, sizes: &ModuleSizes) {
+        if self.builds() == 0 {
+            return;
+        }
+
+        let outcome = if self.failures > 0 {
+            "failed"
+        } else {
+            "succeeded"
+        };
+        let sizes = size_labels(sizes)
+            .into_iter()
+            .map(|(label, size)| format!("{label} {size} bytes"))
+            .collect::<Vec<_>>();
+        if sizes.is_empty() {
+            info!("Build {outcome} in {:.2?}", self.total_time);
+        } else {
+            info!(
+                "Build {outcome} in {:.2?}: {}",
+                self.total_time,
+                sizes.join(", ")
+            );
+        }
+    }
+
+    /// Format the accumulated statistics as a JSON `summary` event, for `--message-format json`.
+    pub fn to_json(&self, sizes: &ModuleSizes) -> Value {
+        serde_json::json!({
+            "event": "summary",
+            "builds": self.builds(),
+            "successes": self.successes,
+            "failures": self.failures,
+            "total_time_ms": self.total_time.as_millis() as u64,
+            "average_time_ms": self.average_time().map(|time| time.as_millis() as u64),
+            "slowest_time_ms": self.max_time.map(|time| time.as_millis() as u64),
+            "module_sizes": sizes.iter().map(|((target, name), size)| serde_json::json!({
+                "target": target,
+                "name": name,
+                "bytes": size,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Time spent in each phase of a single build.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BuildTimings {
+    /// `cargo check` pre-pass, if enabled.
+    pub check: Option<Duration>,
+    /// SPIR-V compile via `spirv-builder`.
+    pub compile: Duration,
+    /// Writing outputs to disk.
+    pub write: Option<Duration>,
+}
+
+impl BuildTimings {
+    /// Time spent checking and compiling, excluding output.
+    pub fn build(&self) -> Duration {
+        self.check.unwrap_or_default() + self.compile
+    }
+
+    /// Time spent on every phase.
+    pub fn total(&self) -> Duration {
+        self.build() + self.write.unwrap_or_default()
+    }
+}
+
+impl Display for BuildTimings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.2?} (", self.total())?;
+
+        if let Some(check) = self.check {
+            write!(f, "check {check:.2?}, ")?;
+        }
+
+        write!(f, "compile {:.2?}", self.compile)?;
+
+        if let Some(write) = self.write {
+            write!(f, ", write {write:.2?}")?;
+        }
+
+        f.write_str(")")
+    }
+}
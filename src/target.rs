@@ -0,0 +1,553 @@
+//! SPIR-V target triples, and the shorthands accepted for them.
+
+/// SPIR-V targets supported by rust-gpu.
+pub const KNOWN_TARGETS: &[&str] = &[
+    "spirv-unknown-spv1.0",
+    "spirv-unknown-spv1.1",
+    "spirv-unknown-spv1.2",
+    "spirv-unknown-spv1.3",
+    "spirv-unknown-spv1.4",
+    "spirv-unknown-spv1.5",
+    "spirv-unknown-vulkan1.0",
+    "spirv-unknown-vulkan1.1",
+    "spirv-unknown-vulkan1.1spv1.4",
+    "spirv-unknown-vulkan1.2",
+    "spirv-unknown-opengl4.0",
+    "spirv-unknown-opengl4.1",
+    "spirv-unknown-opengl4.2",
+    "spirv-unknown-opengl4.3",
+    "spirv-unknown-opengl4.5",
+    "spirv-unknown-webgpu0",
+];
+
+const PREFIX: &str = "spirv-unknown-";
+
+/// Normalize a `--target` value into a full target triple.
+///
+/// Full triples are passed through as-is, while shorthands omitting the `spirv-unknown-`
+/// prefix, ex. `vulkan1.2`, must expand to one of [`KNOWN_TARGETS`].
+pub fn normalize(target: &str) -> Result<String, String> {
+    if target.starts_with(PREFIX) {
+        return Ok(target.to_string());
+    }
+
+    let triple = format!("{PREFIX}{target}");
+    if KNOWN_TARGETS.contains(&triple.as_str()) {
+        Ok(triple)
+    } else {
+        Err(format!(
+            "Unknown target {target:?}, expected a target triple or one of: {}",
+            shorthands()
+        ))
+    }
+}
+
+/// The target triple for a `--vulkan` version, ex. `1.2`.
+pub fn vulkan(version: &str) -> Result<String, String> {
+    known(format!("{PREFIX}vulkan{version}"), "Vulkan", version)
+}
+
+/// The target triple for a `--spirv-version`, ex. `1.5`.
+pub fn spirv(version: &str) -> Result<String, String> {
+    known(format!("{PREFIX}spv{version}"), "SPIR-V", version)
+}
+
+/// Check that `triple`, as derived from an `api` version, is a known target.
+fn known(triple: String, api: &str, version: &str) -> Result<String, String> {
+    if KNOWN_TARGETS.contains(&triple.as_str()) {
+        Ok(triple)
+    } else {
+        Err(format!(
+            "Unsupported {api} version {version:?}, expected a target among: {}",
+            shorthands()
+        ))
+    }
+}
+
+/// The shorthands of all known targets, for error messages.
+fn shorthands() -> String {
+    KNOWN_TARGETS
+        .iter()
+        .map(|target| target.trim_start_matches(PREFIX))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Capabilities of OpenCL kernels, which graphics environments don't accept.
+const KERNEL_CAPABILITIES: &[&str] = &[
+    "Kernel",
+    "Addresses",
+    "Vector16",
+    "Float16Buffer",
+    "ImageBasic",
+    "ImageReadWrite",
+    "ImageMipmap",
+    "Pipes",
+    "DeviceEnqueue",
+    "LiteralSampler",
+    "GenericPointer",
+    "SubgroupDispatch",
+    "PipeStorage",
+    "NamedBarrier",
+];
+
+/// Capabilities introduced by SPIR-V versions after 1.0, with the version adding them to the core specification.
+const CAPABILITY_VERSIONS: &[(&str, (u8, u8))] = &[
+    ("GroupNonUniform", (1, 3)),
+    ("GroupNonUniformVote", (1, 3)),
+    ("GroupNonUniformArithmetic", (1, 3)),
+    ("GroupNonUniformBallot", (1, 3)),
+    ("GroupNonUniformShuffle", (1, 3)),
+    ("GroupNonUniformShuffleRelative", (1, 3)),
+    ("GroupNonUniformClustered", (1, 3)),
+    ("GroupNonUniformQuad", (1, 3)),
+    ("DeviceGroup", (1, 3)),
+    ("MultiView", (1, 3)),
+    ("VariablePointers", (1, 3)),
+    ("VariablePointersStorageBuffer", (1, 3)),
+    ("DrawParameters", (1, 3)),
+    ("StorageBuffer16BitAccess", (1, 3)),
+    ("UniformAndStorageBuffer16BitAccess", (1, 3)),
+    ("StoragePushConstant16", (1, 3)),
+    ("StorageInputOutput16", (1, 3)),
+    ("DenormPreserve", (1, 4)),
+    ("DenormFlushToZero", (1, 4)),
+    ("SignedZeroInfNanPreserve", (1, 4)),
+    ("RoundingModeRTE", (1, 4)),
+    ("RoundingModeRTZ", (1, 4)),
+    ("StorageBuffer8BitAccess", (1, 5)),
+    ("UniformAndStorageBuffer8BitAccess", (1, 5)),
+    ("StoragePushConstant8", (1, 5)),
+    ("ShaderNonUniform", (1, 5)),
+    ("RuntimeDescriptorArray", (1, 5)),
+    ("InputAttachmentArrayDynamicIndexing", (1, 5)),
+    ("UniformTexelBufferArrayDynamicIndexing", (1, 5)),
+    ("StorageTexelBufferArrayDynamicIndexing", (1, 5)),
+    ("UniformBufferArrayNonUniformIndexing", (1, 5)),
+    ("SampledImageArrayNonUniformIndexing", (1, 5)),
+    ("StorageBufferArrayNonUniformIndexing", (1, 5)),
+    ("StorageImageArrayNonUniformIndexing", (1, 5)),
+    ("InputAttachmentArrayNonUniformIndexing", (1, 5)),
+    ("UniformTexelBufferArrayNonUniformIndexing", (1, 5)),
+    ("StorageTexelBufferArrayNonUniformIndexing", (1, 5)),
+    ("VulkanMemoryModel", (1, 5)),
+    ("VulkanMemoryModelDeviceScope", (1, 5)),
+    ("PhysicalStorageBufferAddresses", (1, 5)),
+    ("ShaderViewportIndex", (1, 5)),
+    ("ShaderLayer", (1, 5)),
+];
+
+/// The SPIR-V version a known target emits, ex. 1.5 for `spirv-unknown-vulkan1.2`.
+fn spirv_version(target: &str) -> Option<(u8, u8)> {
+    let env = target.strip_prefix(PREFIX)?;
+    let version = match env {
+        "vulkan1.0" => (1, 0),
+        "vulkan1.1" | "webgpu0" => (1, 3),
+        "vulkan1.1spv1.4" => (1, 4),
+        "vulkan1.2" => (1, 5),
+        _ if env.starts_with("opengl") => (1, 0),
+        _ => {
+            let (major, minor) = env.strip_prefix("spv")?.split_once('.')?;
+            (major.parse().ok()?, minor.parse().ok()?)
+        }
+    };
+    Some(version)
+}
+
+/// Check that `capability`, as named by its `Debug` representation, is valid for `target`.
+///
+/// Only covers kernel capabilities in graphics environments, and capabilities newer than
+/// the target's SPIR-V version. Unknown targets and capabilities are assumed valid.
+pub fn check_capability(target: &str, capability: &str) -> Result<(), String> {
+    let graphics = !target.starts_with(&format!("{PREFIX}spv"));
+    if graphics && KERNEL_CAPABILITIES.contains(&capability) {
+        return Err(format!(
+            "Capability {capability} is only available to OpenCL kernels, which {target} doesn't support"
+        ));
+    }
+
+    let Some(version) = spirv_version(target) else {
+        return Ok(())
+    };
+
+    match CAPABILITY_VERSIONS
+        .iter()
+        .find(|(name, _)| *name == capability)
+    {
+        Some((_, required)) if *required > version => Err(format!(
+            "Capability {capability} requires SPIR-V {}.{}, but {target} emits SPIR-V {}.{}; \
+             pick a --target emitting SPIR-V {}.{} or later",
+            required.0, required.1, version.0, version.1, required.0, required.1
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Vulkan requirements of capabilities beyond Vulkan 1.0 core: the earliest Vulkan version
+/// supporting the capability, the extension it needs there if any, and the version it became core in.
+const VULKAN_CAPABILITIES: &[(&str, (u8, u8), Option<&str>, Option<(u8, u8)>)] = &[
+    (
+        "DrawParameters",
+        (1, 0),
+        Some("VK_KHR_shader_draw_parameters"),
+        Some((1, 1)),
+    ),
+    ("MultiView", (1, 0), Some("VK_KHR_multiview"), Some((1, 1))),
+    (
+        "DeviceGroup",
+        (1, 0),
+        Some("VK_KHR_device_group"),
+        Some((1, 1)),
+    ),
+    (
+        "VariablePointers",
+        (1, 0),
+        Some("VK_KHR_variable_pointers"),
+        Some((1, 1)),
+    ),
+    (
+        "VariablePointersStorageBuffer",
+        (1, 0),
+        Some("VK_KHR_variable_pointers"),
+        Some((1, 1)),
+    ),
+    (
+        "StorageBuffer16BitAccess",
+        (1, 0),
+        Some("VK_KHR_16bit_storage"),
+        Some((1, 1)),
+    ),
+    (
+        "UniformAndStorageBuffer16BitAccess",
+        (1, 0),
+        Some("VK_KHR_16bit_storage"),
+        Some((1, 1)),
+    ),
+    (
+        "StoragePushConstant16",
+        (1, 0),
+        Some("VK_KHR_16bit_storage"),
+        Some((1, 1)),
+    ),
+    (
+        "StorageInputOutput16",
+        (1, 0),
+        Some("VK_KHR_16bit_storage"),
+        Some((1, 1)),
+    ),
+    ("GroupNonUniform", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformVote", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformArithmetic", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformBallot", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformShuffle", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformShuffleRelative", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformClustered", (1, 1), None, Some((1, 1))),
+    ("GroupNonUniformQuad", (1, 1), None, Some((1, 1))),
+    (
+        "StorageBuffer8BitAccess",
+        (1, 0),
+        Some("VK_KHR_8bit_storage"),
+        Some((1, 2)),
+    ),
+    (
+        "UniformAndStorageBuffer8BitAccess",
+        (1, 0),
+        Some("VK_KHR_8bit_storage"),
+        Some((1, 2)),
+    ),
+    (
+        "StoragePushConstant8",
+        (1, 0),
+        Some("VK_KHR_8bit_storage"),
+        Some((1, 2)),
+    ),
+    (
+        "Float16",
+        (1, 0),
+        Some("VK_KHR_shader_float16_int8"),
+        Some((1, 2)),
+    ),
+    (
+        "Int8",
+        (1, 0),
+        Some("VK_KHR_shader_float16_int8"),
+        Some((1, 2)),
+    ),
+    (
+        "Int64Atomics",
+        (1, 0),
+        Some("VK_KHR_shader_atomic_int64"),
+        Some((1, 2)),
+    ),
+    (
+        "DenormPreserve",
+        (1, 0),
+        Some("VK_KHR_shader_float_controls"),
+        Some((1, 2)),
+    ),
+    (
+        "DenormFlushToZero",
+        (1, 0),
+        Some("VK_KHR_shader_float_controls"),
+        Some((1, 2)),
+    ),
+    (
+        "SignedZeroInfNanPreserve",
+        (1, 0),
+        Some("VK_KHR_shader_float_controls"),
+        Some((1, 2)),
+    ),
+    (
+        "RoundingModeRTE",
+        (1, 0),
+        Some("VK_KHR_shader_float_controls"),
+        Some((1, 2)),
+    ),
+    (
+        "RoundingModeRTZ",
+        (1, 0),
+        Some("VK_KHR_shader_float_controls"),
+        Some((1, 2)),
+    ),
+    (
+        "ShaderNonUniform",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "RuntimeDescriptorArray",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "InputAttachmentArrayDynamicIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "UniformTexelBufferArrayDynamicIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "StorageTexelBufferArrayDynamicIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "UniformBufferArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "SampledImageArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "StorageBufferArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "StorageImageArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "InputAttachmentArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "UniformTexelBufferArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "StorageTexelBufferArrayNonUniformIndexing",
+        (1, 0),
+        Some("VK_EXT_descriptor_indexing"),
+        Some((1, 2)),
+    ),
+    (
+        "VulkanMemoryModel",
+        (1, 0),
+        Some("VK_KHR_vulkan_memory_model"),
+        Some((1, 2)),
+    ),
+    (
+        "VulkanMemoryModelDeviceScope",
+        (1, 0),
+        Some("VK_KHR_vulkan_memory_model"),
+        Some((1, 2)),
+    ),
+    (
+        "PhysicalStorageBufferAddresses",
+        (1, 0),
+        Some("VK_KHR_buffer_device_address"),
+        Some((1, 2)),
+    ),
+    ("ShaderViewportIndex", (1, 2), None, Some((1, 2))),
+    ("ShaderLayer", (1, 2), None, Some((1, 2))),
+    (
+        "ShaderViewportIndexLayerEXT",
+        (1, 0),
+        Some("VK_EXT_shader_viewport_index_layer"),
+        None,
+    ),
+    (
+        "SubgroupBallotKHR",
+        (1, 0),
+        Some("VK_EXT_shader_subgroup_ballot"),
+        None,
+    ),
+    (
+        "SubgroupVoteKHR",
+        (1, 0),
+        Some("VK_EXT_shader_subgroup_vote"),
+        None,
+    ),
+    (
+        "StencilExportEXT",
+        (1, 0),
+        Some("VK_EXT_shader_stencil_export"),
+        None,
+    ),
+    ("ShaderClockKHR", (1, 0), Some("VK_KHR_shader_clock"), None),
+    (
+        "DemoteToHelperInvocationEXT",
+        (1, 0),
+        Some("VK_EXT_shader_demote_to_helper_invocation"),
+        None,
+    ),
+    (
+        "FragmentShadingRateKHR",
+        (1, 0),
+        Some("VK_KHR_fragment_shading_rate"),
+        None,
+    ),
+    (
+        "AtomicFloat32AddEXT",
+        (1, 0),
+        Some("VK_EXT_shader_atomic_float"),
+        None,
+    ),
+    (
+        "AtomicFloat64AddEXT",
+        (1, 0),
+        Some("VK_EXT_shader_atomic_float"),
+        None,
+    ),
+    (
+        "Int64ImageEXT",
+        (1, 0),
+        Some("VK_EXT_shader_image_atomic_int64"),
+        None,
+    ),
+    ("MeshShadingNV", (1, 0), Some("VK_NV_mesh_shader"), None),
+    ("RayTracingNV", (1, 0), Some("VK_NV_ray_tracing"), None),
+    (
+        "RayTracingKHR",
+        (1, 2),
+        Some("VK_KHR_ray_tracing_pipeline"),
+        None,
+    ),
+    ("RayQueryKHR", (1, 2), Some("VK_KHR_ray_query"), None),
+];
+
+/// What a SPIR-V capability requires of a Vulkan implementation.
+pub struct VulkanRequirement {
+    /// The earliest Vulkan version supporting the capability.
+    pub version: (u8, u8),
+    /// The extension needed to use the capability on `version`, if any.
+    pub extension: Option<&'static str>,
+    /// The Vulkan version the capability became core in, if any.
+    pub core: Option<(u8, u8)>,
+}
+
+/// What `capability`, as named by its `Debug` representation, requires of a Vulkan implementation.
+///
+/// Capabilities missing from the lookup table are assumed to be Vulkan 1.0 core.
+pub fn vulkan_requirement(capability: &str) -> VulkanRequirement {
+    match VULKAN_CAPABILITIES
+        .iter()
+        .find(|(name, ..)| *name == capability)
+    {
+        Some((_, version, extension, core)) => VulkanRequirement {
+            version: *version,
+            extension: *extension,
+            core: *core,
+        },
+        None => VulkanRequirement {
+            version: (1, 0),
+            extension: None,
+            core: Some((1, 0)),
+        },
+    }
+}
+
+/// The Vulkan version a known target is for, ex. 1.1 for `spirv-unknown-vulkan1.1spv1.4`.
+pub fn vulkan_version(target: &str) -> Option<(u8, u8)> {
+    match target.strip_prefix(PREFIX)? {
+        "vulkan1.0" => Some((1, 0)),
+        "vulkan1.1" | "vulkan1.1spv1.4" => Some((1, 1)),
+        "vulkan1.2" => Some((1, 2)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shorthands_expand_to_known_targets() {
+        for target in KNOWN_TARGETS {
+            let shorthand = target.strip_prefix(PREFIX).unwrap();
+            assert_eq!(normalize(shorthand).as_deref(), Ok(*target));
+            assert_eq!(normalize(target).as_deref(), Ok(*target));
+        }
+    }
+
+    #[test]
+    fn version_flags_expand_to_known_targets() {
+        for target in KNOWN_TARGETS {
+            let shorthand = target.strip_prefix(PREFIX).unwrap();
+            if let Some(version) = shorthand.strip_prefix("vulkan") {
+                assert_eq!(vulkan(version).as_deref(), Ok(*target));
+            } else if let Some(version) = shorthand.strip_prefix("spv") {
+                assert_eq!(spirv(version).as_deref(), Ok(*target));
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_shorthands_are_rejected() {
+        assert!(normalize("vulkan9.9").is_err());
+        assert!(normalize("spv2.0").is_err());
+        assert!(normalize("").is_err());
+        assert!(vulkan("1.3").is_err());
+        assert!(vulkan("").is_err());
+        assert!(spirv("1.6").is_err());
+        assert!(spirv("vulkan1.2").is_err());
+    }
+
+    #[test]
+    fn errors_list_the_shorthands() {
+        let e = vulkan("1.3").unwrap_err();
+        assert!(e.contains("\"1.3\""), "{e}");
+        assert!(e.contains("vulkan1.2"), "{e}");
+    }
+}
@@ -0,0 +1,137 @@
+//! Output filename templates, as used by `--output-template`.
+
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use rspirv::spirv::ExecutionModel;
+use sha2::{Digest, Sha256};
+
+use crate::{reflect, source_hash::to_hex};
+
+/// A piece of an output template.
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Name,
+    Target,
+    /// The first N hex characters of the module's SHA-256 hash.
+    Hash(usize),
+    Stage,
+    Ext,
+}
+
+/// A filename template containing `{name}`, `{target}`, `{hash}`, `{hash:N}`, `{sha256}`,
+/// `{stage}` and `{ext}` variables.
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed '{{' in output template {s:?}"))?;
+            let variable = &rest[start + 1..start + end];
+
+            parts.push(match variable.split_once(':') {
+                None if variable == "name" => Part::Name,
+                None if variable == "target" => Part::Target,
+                None if variable == "hash" => Part::Hash(8),
+                None if variable == "sha256" => Part::Hash(64),
+                None if variable == "stage" => Part::Stage,
+                None if variable == "ext" => Part::Ext,
+                Some(("hash", len)) => match len.parse() {
+                    Ok(len @ 1..=64) => Part::Hash(len),
+                    _ => return Err(format!("Invalid hash length {len:?}, expected 1 to 64")),
+                },
+                _ => return Err(format!("Unknown output template variable {{{variable}}}")),
+            });
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(OutputTemplate { parts })
+    }
+}
+
+impl Display for OutputTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => f.write_str(literal)?,
+                Part::Name => f.write_str("{name}")?,
+                Part::Target => f.write_str("{target}")?,
+                Part::Hash(len) => write!(f, "{{hash:{len}}}")?,
+                Part::Stage => f.write_str("{stage}")?,
+                Part::Ext => f.write_str("{ext}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputTemplate {
+    /// Whether the template contains a `{target}` variable.
+    pub fn uses_target(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Target))
+    }
+
+    /// Render the filename for a module.
+    pub fn render(&self, name: &str, target: &str, module: &[u8], ext: &str) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Name => name.to_string(),
+                Part::Target => target.to_string(),
+                Part::Hash(len) => to_hex(&Sha256::digest(module))[..*len].to_string(),
+                Part::Stage => stage(module),
+                Part::Ext => ext.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Short name for the pipeline stage of a module's entry points, ex. `vert` or `frag`.
+///
+/// Modules whose entry points span several stages are named `mixed`.
+fn stage(module: &[u8]) -> String {
+    let mut models = vec![];
+    for (_, model) in reflect::entry_points(module).unwrap_or_default() {
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+
+    match models.as_slice() {
+        [model] => match model {
+            ExecutionModel::Vertex => "vert".to_string(),
+            ExecutionModel::TessellationControl => "tesc".to_string(),
+            ExecutionModel::TessellationEvaluation => "tese".to_string(),
+            ExecutionModel::Geometry => "geom".to_string(),
+            ExecutionModel::Fragment => "frag".to_string(),
+            ExecutionModel::GLCompute => "comp".to_string(),
+            other => format!("{other:?}").to_lowercase(),
+        },
+        [] => "none".to_string(),
+        _ => "mixed".to_string(),
+    }
+}
@@ -0,0 +1,105 @@
+//! Invocation of external SPIR-V tools on built modules.
+
+use std::{
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tracing::{info, warn};
+
+/// Where external SPIR-V tools are looked up.
+#[derive(Debug, Default, Clone)]
+pub struct ToolPaths {
+    /// Directory containing the tools, as per `--external-tools-path`.
+    pub dir: Option<PathBuf>,
+    /// Explicit path to `spirv-dis`, taking precedence over everything else.
+    pub spirv_dis: Option<PathBuf>,
+}
+
+impl ToolPaths {
+    /// The `spirv-dis` executable to invoke, if found.
+    pub fn spirv_dis(&self) -> Option<PathBuf> {
+        self.resolve("spirv-dis", self.spirv_dis.as_deref())
+    }
+
+    /// Every tool the builder may invoke, along with its resolved path.
+    pub fn list(&self) -> Vec<(&'static str, Option<PathBuf>)> {
+        vec![("spirv-dis", self.spirv_dis())]
+    }
+
+    /// Look up the tool `name`, checking in order:
+    /// - The explicit `path`, ex. `--spirv-dis-path`
+    /// - The environment variable named after the tool, ex. `SPIRV_DIS`
+    /// - `--external-tools-path`
+    /// - `$VULKAN_SDK/bin`
+    /// - `$HOME/.local/bin`
+    /// - `PATH`
+    ///
+    /// Explicitly configured paths are returned as-is, while the remaining locations
+    /// are only used if the tool exists there.
+    fn resolve(&self, name: &str, path: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = path {
+            return Some(path.to_path_buf());
+        }
+
+        let var = name.to_uppercase().replace('-', "_");
+        if let Some(path) = std::env::var_os(var).filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+
+        let file_name = format!("{name}{}", std::env::consts::EXE_SUFFIX);
+        if let Some(dir) = &self.dir {
+            return Some(dir.join(file_name));
+        }
+
+        let sdk = std::env::var_os("VULKAN_SDK").map(|sdk| PathBuf::from(sdk).join("bin"));
+        let local = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/bin"));
+        let path = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        sdk.into_iter()
+            .chain(local)
+            .chain(path)
+            .map(|dir| dir.join(&file_name))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+/// Disassemble the SPIR-V module at `module` into `out` using `spirv-dis`.
+///
+/// A missing `spirv-dis` is reported as a warning rather than an error.
+pub fn disassemble(tools: &ToolPaths, module: &Path, out: &Path) -> io::Result<()> {
+    let Some(spirv_dis) = tools.spirv_dis() else {
+        warn!("spirv-dis not found, skipping disassembly (see --list-tools)");
+        return Ok(());
+    };
+
+    let output = match Command::new(&spirv_dis)
+        .arg(module)
+        .arg("-o")
+        .arg(out)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            warn!("{spirv_dis:?} not found, skipping disassembly");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            ErrorKind::Other,
+            format!(
+                "spirv-dis failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    info!("Wrote disassembly to {out:?}");
+    Ok(())
+}
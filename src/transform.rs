@@ -0,0 +1,205 @@
+//! Post-compilation transformations of SPIR-V modules.
+
+use std::collections::{BTreeMap, HashSet};
+
+use rspirv::{
+    binary::{Assemble, ParseState},
+    dr::{Function, Instruction, Module, Operand},
+    spirv::{Decoration, Op, Word},
+};
+use rust_gpu_builder_shared::RustGpuBuilderModules;
+
+/// Assemble a SPIR-V module into bytes.
+fn assemble(module: &Module) -> Vec<u8> {
+    module
+        .assemble()
+        .into_iter()
+        .flat_map(u32::to_le_bytes)
+        .collect()
+}
+
+/// Parse a SPIR-V module, apply `f` to it, and reassemble it.
+fn transform(bytes: &[u8], f: impl FnOnce(&mut Module)) -> Result<Vec<u8>, ParseState> {
+    let mut module = rspirv::dr::load_bytes(bytes)?;
+    f(&mut module);
+
+    Ok(assemble(&module))
+}
+
+/// Apply a transformation to each module in `modules`.
+pub fn apply(
+    modules: &mut RustGpuBuilderModules,
+    f: impl Fn(&[u8]) -> Result<Vec<u8>, ParseState>,
+) -> Result<(), ParseState> {
+    match modules {
+        RustGpuBuilderModules::Single(module) => *module = f(module)?,
+        RustGpuBuilderModules::Multi(multi) => {
+            for module in multi.values_mut() {
+                *module = f(module)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_debug_line(inst: &Instruction) -> bool {
+    matches!(inst.class.opcode, Op::Line | Op::NoLine)
+}
+
+fn is_debug_decoration(inst: &Instruction) -> bool {
+    matches!(
+        inst.class.opcode,
+        Op::Decorate | Op::DecorateString | Op::MemberDecorate | Op::MemberDecorateString
+    ) && inst.operands.iter().any(|operand| {
+        matches!(
+            operand,
+            Operand::Decoration(
+                Decoration::UserSemantic
+                    | Decoration::UserTypeGOOGLE
+                    | Decoration::HlslSemanticGOOGLE
+            )
+        )
+    })
+}
+
+/// Remove debug information from a SPIR-V module.
+///
+/// Strips source and name information, line annotations and debug-only decorations.
+pub fn strip_debug(bytes: &[u8]) -> Result<Vec<u8>, ParseState> {
+    transform(bytes, |module| {
+        module.debug_string_source.clear();
+        module.debug_names.clear();
+        module.debug_module_processed.clear();
+
+        module.annotations.retain(|inst| !is_debug_decoration(inst));
+        module
+            .types_global_values
+            .retain(|inst| !is_debug_line(inst));
+
+        for function in &mut module.functions {
+            for block in &mut function.blocks {
+                block.instructions.retain(|inst| !is_debug_line(inst));
+            }
+        }
+    })
+}
+
+/// Rename each entry point in a SPIR-V module to `<prefix><name><suffix>`.
+pub fn rename_entry_points(
+    bytes: &[u8],
+    prefix: &str,
+    suffix: &str,
+) -> Result<Vec<u8>, ParseState> {
+    transform(bytes, |module| {
+        for inst in &mut module.entry_points {
+            if let Some(Operand::LiteralString(name)) = inst.operands.get_mut(2) {
+                *name = format!("{prefix}{name}{suffix}");
+            }
+        }
+    })
+}
+
+/// The result ID of a function.
+pub fn function_id(function: &Function) -> Option<Word> {
+    function.def.as_ref().and_then(|def| def.result_id)
+}
+
+/// Whether `inst` refers to one of `ids` by its first operand, as names and decorations do.
+fn targets_any(inst: &Instruction, ids: &HashSet<Word>) -> bool {
+    matches!(inst.operands.first(), Some(Operand::IdRef(id)) if ids.contains(id))
+}
+
+/// The functions reachable from `entry` through function calls, including itself.
+pub fn reachable_functions(module: &Module, entry: Word) -> HashSet<Word> {
+    let mut reachable = HashSet::from([entry]);
+    let mut stack = vec![entry];
+
+    while let Some(id) = stack.pop() {
+        let Some(function) = module
+            .functions
+            .iter()
+            .find(|function| function_id(function) == Some(id))
+        else {
+            continue;
+        };
+
+        for inst in function.blocks.iter().flat_map(|block| &block.instructions) {
+            if inst.class.opcode != Op::FunctionCall {
+                continue;
+            }
+
+            if let Some(Operand::IdRef(callee)) = inst.operands.first() {
+                if reachable.insert(*callee) {
+                    stack.push(*callee);
+                }
+            }
+        }
+    }
+
+    reachable
+}
+
+/// Remove all entry points but `entry` from a module, along with the functions
+/// unreachable from it and any names and decorations of their IDs.
+fn retain_entry_point(module: &mut Module, entry: Word) {
+    let entry_ids = HashSet::from([entry]);
+    module
+        .entry_points
+        .retain(|inst| matches!(inst.operands.get(1), Some(Operand::IdRef(id)) if *id == entry));
+    module
+        .execution_modes
+        .retain(|inst| targets_any(inst, &entry_ids));
+
+    let reachable = reachable_functions(module, entry);
+    let removed = module
+        .functions
+        .iter()
+        .filter(|function| !function_id(function).map_or(false, |id| reachable.contains(&id)))
+        .flat_map(|function| {
+            function
+                .def
+                .iter()
+                .chain(&function.parameters)
+                .chain(
+                    function
+                        .blocks
+                        .iter()
+                        .flat_map(|block| block.label.iter().chain(&block.instructions)),
+                )
+                .filter_map(|inst| inst.result_id)
+        })
+        .collect::<HashSet<_>>();
+
+    module
+        .functions
+        .retain(|function| function_id(function).map_or(false, |id| reachable.contains(&id)));
+    module
+        .debug_names
+        .retain(|inst| !targets_any(inst, &removed));
+    module
+        .annotations
+        .retain(|inst| !targets_any(inst, &removed));
+}
+
+/// Split a SPIR-V module into one module per entry point, keyed by entry point name.
+///
+/// Each module contains only its entry point and the functions reachable from it.
+pub fn split_entry_points(bytes: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, ParseState> {
+    let module = rspirv::dr::load_bytes(bytes)?;
+
+    let mut split = BTreeMap::new();
+    for inst in &module.entry_points {
+        let (Some(Operand::IdRef(entry)), Some(Operand::LiteralString(name))) =
+            (inst.operands.get(1), inst.operands.get(2))
+        else {
+            continue;
+        };
+
+        let mut entry_module = module.clone();
+        retain_entry_point(&mut entry_module, *entry);
+        split.insert(name.clone(), assemble(&entry_module));
+    }
+
+    Ok(split)
+}